@@ -1,3 +1,5 @@
+use crate::conflict::OnConflict;
+use crate::worktree_apply::{EmptyMode, RecordOrigin, SquashMode};
 use clap::{ArgAction, Parser};
 
 #[derive(Parser, Debug)]
@@ -10,7 +12,19 @@ pub struct Args {
     #[arg(long)]
     pub base: Option<String>,
 
-    /// Keep unassigned commits on current branch (default behavior; provided for explicitness)
+    /// Branch (or commit) to extract from instead of HEAD; repeat to gather several branches
+    #[arg(long, value_name = "BRANCH")]
+    pub source: Vec<String>,
+
+    /// Extract exactly the commits in <a>..<b> instead of a branch since its merge-base
+    #[arg(long, value_name = "A..B", conflicts_with = "source")]
+    pub range: Option<String>,
+
+    /// Import an mbox file or a directory of format-patch files (applied with git am -3 on the base)
+    #[arg(long, value_name = "PATH")]
+    pub from_mbox: Option<String>,
+
+    /// Keep unassigned commits on current branch (default behavior; overrides extract.noCurrent)
     #[arg(long, action = ArgAction::SetTrue)]
     pub default_current: bool,
 
@@ -42,14 +56,84 @@ pub struct Args {
     #[arg(long, value_name = "PATH", hide = true)]
     pub routing_file: Option<String>,
 
+    /// Park branches that hit conflicts and keep applying the remaining targets
+    #[arg(long)]
+    pub keep_going: bool,
+
+    /// What to do when a cherry-pick conflicts: stop, run mergetool, or open a shell
+    #[arg(long, value_enum, default_value_t = OnConflict::Stop)]
+    pub on_conflict: OnConflict,
+
+    /// Merge strategy for cherry-picks (passed as --strategy)
+    #[arg(long, value_name = "STRATEGY")]
+    pub strategy: Option<String>,
+
+    /// Strategy option for cherry-picks, e.g. ours, theirs, patience, ignore-space-change
+    #[arg(short = 'X', long, value_name = "OPTION")]
+    pub strategy_option: Vec<String>,
+
+    /// Conflict marker style in the temp worktree
+    #[arg(long, value_parser = crate::routing::CONFLICT_STYLES)]
+    pub conflict_style: Option<String>,
+
+    /// What to do with commits that become empty on their target: drop, keep, or stop
+    #[arg(long, value_enum, default_value_t = EmptyMode::Drop)]
+    pub empty: EmptyMode,
+
+    /// Sign the commits written to targets (default: commit.gpgSign)
+    #[arg(long, overrides_with = "no_sign")]
+    pub sign: bool,
+
+    /// Do not sign the commits written to targets, even with commit.gpgSign set
+    #[arg(long, overrides_with = "sign")]
+    pub no_sign: bool,
+
+    /// Use each commit's author date as its committer date on the targets
+    #[arg(long)]
+    pub committer_date_is_author_date: bool,
+
+    /// Make yourself the author of the commits written to targets
+    #[arg(long)]
+    pub reset_author: bool,
+
+    /// Add a Signed-off-by trailer to the commits written to targets
+    #[arg(long)]
+    pub signoff: bool,
+
+    /// Trace written commits back to their source: none, trailer (Extracted-From:), or notes
+    #[arg(long, value_enum, default_value_t = RecordOrigin::None)]
+    pub record_origin: RecordOrigin,
+
+    /// Collapse the commits of every target into one (=edit opens the message in the editor)
+    #[arg(
+        long,
+        value_enum,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "plain"
+    )]
+    pub squash_targets: Option<SquashMode>,
+
+    /// Move target branches even when they are checked out in a worktree with local changes
+    #[arg(long)]
+    pub force: bool,
+
     /// Resume a previous extract session after conflicts
     #[arg(long, conflicts_with = "abort")]
     pub r#continue: bool,
 
+    /// Session to continue/abort when several are paused
+    #[arg(long, value_name = "ID")]
+    pub session: Option<String>,
+
     /// Abort a previous extract session
     #[arg(long, conflicts_with = "continue")]
     pub abort: bool,
 
+    /// Clean up or reconstruct sessions left behind by a crash or kill
+    #[arg(long, conflicts_with_all = ["continue", "abort"])]
+    pub repair: bool,
+
     /// Do not auto-chdir into conflict worktree on --continue/--abort
     #[arg(long, hide = true)]
     pub no_chdir_conflict: bool,
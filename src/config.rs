@@ -0,0 +1,107 @@
+use crate::cli::Args;
+use crate::conflict::OnConflict;
+use crate::routing::slugify;
+use crate::worktree_apply::RecordOrigin;
+use anyhow::{Result, anyhow};
+use clap::parser::ValueSource;
+use clap::{ArgMatches, ValueEnum};
+use git2::Repository;
+use std::collections::HashMap;
+
+/// Per-repository defaults from the `extract.*` git config keys; command-line flags win.
+///
+/// `extract.base` is read by `git_ops::detect_base` directly.
+#[derive(Debug, Clone, Default)]
+pub struct ExtractConfig {
+    pub no_current: Option<bool>,
+    /// Prepended to target branch names given on the command line (`extract.branchPrefix`).
+    pub branch_prefix: Option<String>,
+    /// Name for `new` destinations (`extract.branchTemplate`), e.g. `{user}/{slug}`.
+    pub branch_template: Option<String>,
+    /// `{user}` in the template: the local part of `user.email`, else `user.name`, slugified.
+    pub user: Option<String>,
+    pub editor: Option<String>,
+    pub on_conflict: Option<OnConflict>,
+    pub record_origin: Option<RecordOrigin>,
+    /// `extract.group.<name>.targets`, referenced as `@<name>` target.
+    pub groups: HashMap<String, Vec<String>>,
+}
+
+impl ExtractConfig {
+    pub fn load(repo: &Repository) -> Result<Self> {
+        let config = repo.config()?.snapshot()?;
+        let string = |key: &str| config.get_string(key).ok().filter(|v| !v.is_empty());
+        let mut loaded = ExtractConfig {
+            no_current: config.get_bool("extract.noCurrent").ok(),
+            branch_prefix: string("extract.branchPrefix"),
+            branch_template: string("extract.branchTemplate"),
+            user: string("user.email")
+                .and_then(|email| email.split('@').next().map(slugify))
+                .or_else(|| string("user.name").map(|name| slugify(&name)))
+                .filter(|user| !user.is_empty()),
+            editor: string("extract.editor"),
+            on_conflict: string("extract.onConflict")
+                .map(|v| parse_enum("extract.onConflict", &v))
+                .transpose()?,
+            record_origin: string("extract.recordOrigin")
+                .map(|v| parse_enum("extract.recordOrigin", &v))
+                .transpose()?,
+            groups: HashMap::new(),
+        };
+        let mut entries = config.entries(Some(r"extract\.group\..*\.targets"))?;
+        while let Some(entry) = entries.next() {
+            let entry = entry?;
+            let (Some(name), Some(value)) = (entry.name(), entry.value()) else {
+                continue;
+            };
+            let group = name
+                .strip_prefix("extract.group.")
+                .and_then(|rest| rest.strip_suffix(".targets"))
+                .unwrap_or_default();
+            loaded.groups.entry(group.to_string()).or_default().extend(
+                value
+                    .split([',', ' '])
+                    .filter(|t| !t.is_empty())
+                    .map(str::to_string),
+            );
+        }
+        Ok(loaded)
+    }
+
+    /// Fill in what the command line left unset.
+    pub fn apply_to_args(&self, args: &mut Args, matches: &ArgMatches) {
+        if !args.no_current
+            && !args.default_current
+            && let Some(no_current) = self.no_current
+        {
+            args.no_current = no_current;
+        }
+        if args.editor.is_none() {
+            args.editor = self.editor.clone();
+        }
+        let defaulted = |id: &str| matches.value_source(id) != Some(ValueSource::CommandLine);
+        if defaulted("on_conflict")
+            && let Some(mode) = self.on_conflict
+        {
+            args.on_conflict = mode;
+        }
+        if defaulted("record_origin")
+            && let Some(mode) = self.record_origin
+        {
+            args.record_origin = mode;
+        }
+    }
+}
+
+fn parse_enum<T: ValueEnum>(key: &str, value: &str) -> Result<T> {
+    T::from_str(value, true).map_err(|_| {
+        let expected: Vec<String> = T::value_variants()
+            .iter()
+            .filter_map(|v| v.to_possible_value().map(|p| p.get_name().to_string()))
+            .collect();
+        anyhow!(
+            "invalid {key} value {value}; expected one of {}",
+            expected.join(", ")
+        )
+    })
+}
@@ -0,0 +1,149 @@
+use crate::state::{Route, SessionState};
+use anyhow::{Context, Result, anyhow};
+use clap::ValueEnum;
+use git2::{Oid, Repository};
+use std::collections::HashSet;
+use std::path::Path;
+use std::process::Command;
+
+/// What to do when a cherry-pick stops on a conflict.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OnConflict {
+    /// Keep the worktree and exit; resolve by hand and run --continue
+    #[default]
+    Stop,
+    /// Run `git mergetool` in the worktree and continue if everything resolved
+    Mergetool,
+    /// Open `$SHELL` in the worktree and continue when it exits
+    Shell,
+}
+
+/// Run the conflict helper inside `wt_path`.
+///
+/// Returns `true` when the worktree has no unmerged paths left and the session can resume.
+pub fn run_conflict_tool(mode: OnConflict, wt_path: &Path) -> Result<bool> {
+    match mode {
+        OnConflict::Stop => return Ok(false),
+        OnConflict::Mergetool => {
+            let status = Command::new("git")
+                .arg("-C")
+                .arg(wt_path)
+                .arg("mergetool")
+                .status()
+                .context("launching git mergetool")?;
+            if !status.success() {
+                eprintln!("note: git mergetool exited with an error");
+            }
+        }
+        OnConflict::Shell => {
+            let shell = std::env::var("SHELL")
+                .ok()
+                .filter(|s| !s.trim().is_empty())
+                .unwrap_or_else(|| "sh".to_string());
+            println!(
+                "Starting {shell} in {}; exit the shell to continue",
+                wt_path.display()
+            );
+            Command::new(&shell)
+                .current_dir(wt_path)
+                .status()
+                .with_context(|| format!("launching {shell}"))?;
+        }
+    }
+    let unresolved = unmerged_paths(wt_path)?;
+    if !unresolved.is_empty() {
+        eprintln!(
+            "note: unresolved conflicts remain: {}",
+            unresolved.join(", ")
+        );
+        return Ok(false);
+    }
+    Ok(true)
+}
+
+/// Paths that still have conflict stages in the index of `wt_path`.
+pub fn unmerged_paths(wt_path: &Path) -> Result<Vec<String>> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(wt_path)
+        .args(["diff", "--name-only", "--diff-filter=U"])
+        .output()
+        .context("listing unmerged paths")?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "git diff failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|l| l.to_string())
+        .collect())
+}
+
+/// Point conflicted paths at earlier source commits that were routed away from `branch_idx`.
+///
+/// For every unmerged path, reports the most recent commit before the failing one that touched
+/// the same file but is not part of this branch, e.g.
+/// `conflict in src/db.rs likely depends on 1a2b3c4 (routed to current)`.
+pub fn explain_conflict(
+    repo: &Repository,
+    state: &SessionState,
+    branch_idx: usize,
+    wt_path: &Path,
+) -> Result<Vec<String>> {
+    let queue = &state.branch_queues[branch_idx];
+    let Some(failing) = queue.commits.first() else {
+        return Ok(Vec::new());
+    };
+    let Some(failing_pos) = state.routes.iter().position(|r| &r.sha == failing) else {
+        return Ok(Vec::new());
+    };
+    let earlier: Vec<&Route> = state.routes[..failing_pos]
+        .iter()
+        .filter(|r| r.dest != queue.branch)
+        .collect();
+
+    let mut touched: Vec<(&Route, HashSet<String>)> = Vec::new();
+    for route in earlier {
+        touched.push((route, touched_paths(repo, &route.sha)?));
+    }
+
+    let mut lines = Vec::new();
+    for path in unmerged_paths(wt_path)? {
+        if let Some((route, _)) = touched
+            .iter()
+            .rev()
+            .find(|(_, paths)| paths.contains(&path))
+        {
+            let short = &route.sha[..7.min(route.sha.len())];
+            let how = match route.dest.as_str() {
+                "drop" => "dropped".to_string(),
+                dest => format!("routed to {dest}"),
+            };
+            lines.push(format!(
+                "conflict in {path} likely depends on {short} ({how})"
+            ));
+        }
+    }
+    Ok(lines)
+}
+
+fn touched_paths(repo: &Repository, sha: &str) -> Result<HashSet<String>> {
+    let commit = repo.find_commit(Oid::from_str(sha)?)?;
+    let tree = commit.tree()?;
+    let parent_tree = match commit.parent(0) {
+        Ok(parent) => Some(parent.tree()?),
+        Err(_) => None,
+    };
+    let diff = repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&tree), None)?;
+    let mut paths = HashSet::new();
+    for delta in diff.deltas() {
+        for file in [delta.old_file(), delta.new_file()] {
+            if let Some(p) = file.path() {
+                paths.insert(p.to_string_lossy().into_owned());
+            }
+        }
+    }
+    Ok(paths)
+}
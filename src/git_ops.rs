@@ -1,10 +1,14 @@
 use anyhow::{Context, Result, anyhow};
-use git2::{Oid, Repository, Sort};
+use git2::{Oid, Repository, Sort, Tree};
+use std::collections::HashSet;
+use std::path::Path;
 use std::process::Command;
 
 #[derive(Debug, Clone)]
 pub struct BaseInfo {
     pub base_oid: Oid,
+    /// Ref the base was resolved from; `None` when falling back to HEAD.
+    pub base_ref: Option<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -12,14 +16,32 @@ pub struct CommitInfo {
     pub oid: Oid,
     pub short: String,
     pub summary: String,
+    /// Source the commit was collected from (see `SourceInfo::name`).
+    pub source: String,
+    pub parents: usize,
+    /// For merges: the commits the merge brought in (not on its first parent), oldest first.
+    pub merged: Vec<Oid>,
 }
 
-pub fn ensure_clean(repo: &Repository, allow_dirty: bool) -> Result<()> {
-    if repo.head_detached()? {
-        return Err(anyhow!(
-            "detached HEAD is not supported; please check out a branch"
-        ));
+impl CommitInfo {
+    pub fn is_merge(&self) -> bool {
+        self.parents > 1
     }
+}
+
+/// Where the commits to extract come from.
+#[derive(Debug, Clone)]
+pub struct SourceInfo {
+    /// Name as given on the command line (or the checked-out branch), used in the routing file.
+    pub name: String,
+    /// Full ref name of the source branch, if it is one.
+    pub refname: Option<String>,
+    pub tip: Oid,
+    /// Exclusive lower bound: merge-base with the base, or the left side of `--range`.
+    pub start: Oid,
+}
+
+pub fn ensure_clean(repo: &Repository, allow_dirty: bool) -> Result<()> {
     if allow_dirty {
         return Ok(());
     }
@@ -40,15 +62,23 @@ pub fn ensure_clean(repo: &Repository, allow_dirty: bool) -> Result<()> {
     Ok(())
 }
 
+/// `--base`, else `extract.base`, else the first of origin/main, main, origin/master, master.
 pub fn detect_base(repo: &Repository, user_base: Option<String>) -> Result<BaseInfo> {
-    if let Some(base) = user_base {
+    let configured = || repo.config().ok()?.get_string("extract.base").ok();
+    if let Some(base) = user_base.or_else(configured).filter(|b| !b.is_empty()) {
         let oid = resolve_ref(repo, &base)?;
-        return Ok(BaseInfo { base_oid: oid });
+        return Ok(BaseInfo {
+            base_oid: oid,
+            base_ref: Some(base),
+        });
     }
 
     for candidate in ["origin/main", "main", "origin/master", "master"] {
         if let Ok(oid) = resolve_ref(repo, candidate) {
-            return Ok(BaseInfo { base_oid: oid });
+            return Ok(BaseInfo {
+                base_oid: oid,
+                base_ref: Some(candidate.to_string()),
+            });
         }
     }
 
@@ -57,7 +87,10 @@ pub fn detect_base(repo: &Repository, user_base: Option<String>) -> Result<BaseI
         "note: no main/master found; defaulting base to HEAD ({})",
         head
     );
-    Ok(BaseInfo { base_oid: head })
+    Ok(BaseInfo {
+        base_oid: head,
+        base_ref: None,
+    })
 }
 
 fn resolve_ref(repo: &Repository, name: &str) -> Result<Oid> {
@@ -67,14 +100,117 @@ fn resolve_ref(repo: &Repository, name: &str) -> Result<Oid> {
     Err(anyhow!("unable to resolve ref {name}"))
 }
 
-pub fn collect_commits(repo: &Repository, base_oid: Oid) -> Result<Vec<CommitInfo>> {
-    let head = repo.head()?.target().context("HEAD has no target")?;
-    let merge_base = repo.merge_base(head, base_oid).unwrap_or(base_oid);
+/// Resolve `--source` (repeatable) or `--range`; without either, the checked-out branch is the source.
+pub fn resolve_sources(
+    repo: &Repository,
+    sources: &[String],
+    range: Option<&str>,
+    base_oid: Oid,
+) -> Result<Vec<SourceInfo>> {
+    if range.is_some() || sources.is_empty() {
+        return Ok(vec![resolve_source(repo, None, range, base_oid)?]);
+    }
+    let mut resolved: Vec<SourceInfo> = Vec::new();
+    for name in sources {
+        let source = resolve_source(repo, Some(name), None, base_oid)?;
+        if !resolved.iter().any(|s| s.name == source.name) {
+            resolved.push(source);
+        }
+    }
+    Ok(resolved)
+}
+
+fn resolve_source(
+    repo: &Repository,
+    source: Option<&str>,
+    range: Option<&str>,
+    base_oid: Oid,
+) -> Result<SourceInfo> {
+    if let Some(range) = range {
+        if range.contains("...") {
+            return Err(anyhow!(
+                "symmetric ranges are not supported; use --range <a>..<b>"
+            ));
+        }
+        let (from, to) = range
+            .split_once("..")
+            .ok_or_else(|| anyhow!("invalid range {range}; expected <a>..<b>"))?;
+        let to = if to.is_empty() { "HEAD" } else { to };
+        let start = resolve_commit(repo, from)?;
+        let (refname, tip) = resolve_named(repo, to)?;
+        return Ok(SourceInfo {
+            name: range.to_string(),
+            refname,
+            tip,
+            start,
+        });
+    }
+    let (name, refname, tip) = match source {
+        Some(name) => {
+            let (refname, tip) = resolve_named(repo, name)?;
+            (name.to_string(), refname, tip)
+        }
+        None => {
+            if repo.head_detached()? {
+                return Err(anyhow!(
+                    "detached HEAD; check out a branch or pass --source/--range"
+                ));
+            }
+            let head = repo.head()?;
+            let tip = head.target().context("HEAD has no target")?;
+            let name = head.shorthand().unwrap_or("HEAD").to_string();
+            (name, head.name().map(str::to_string), tip)
+        }
+    };
+    let start = repo.merge_base(tip, base_oid).unwrap_or(base_oid);
+    Ok(SourceInfo {
+        name,
+        refname,
+        tip,
+        start,
+    })
+}
+
+/// Resolve a revision to a commit, keeping the ref name when it names a branch.
+fn resolve_named(repo: &Repository, name: &str) -> Result<(Option<String>, Oid)> {
+    let tip = resolve_commit(repo, name)?;
+    let refname = repo
+        .resolve_reference_from_short_name(name)
+        .ok()
+        .filter(|r| r.is_branch() || r.is_remote())
+        .and_then(|r| r.name().map(str::to_string));
+    Ok((refname, tip))
+}
+
+fn resolve_commit(repo: &Repository, name: &str) -> Result<Oid> {
+    let obj = repo
+        .revparse_single(name)
+        .map_err(|_| anyhow!("unable to resolve {name}"))?;
+    Ok(obj.peel_to_commit()?.id())
+}
+
+/// List the commits of every source, oldest first per source; a commit shared by several
+/// sources is listed once, under the first one.
+pub fn collect_commits(repo: &Repository, sources: &[SourceInfo]) -> Result<Vec<CommitInfo>> {
+    let mut seen: HashSet<Oid> = HashSet::new();
+    let mut all = Vec::new();
+    for source in sources {
+        let mut commits = source_commits(repo, source)?;
+        commits.retain(|c| seen.insert(c.oid));
+        all.extend(commits);
+    }
+    Ok(all)
+}
+
+fn source_commits(repo: &Repository, source: &SourceInfo) -> Result<Vec<CommitInfo>> {
+    let merge_base = source.start;
 
     let mut revwalk = repo.revwalk()?;
-    revwalk.push(head)?;
+    revwalk.push(source.tip)?;
     revwalk.hide(merge_base)?;
     revwalk.set_sorting(Sort::TOPOLOGICAL | Sort::TIME)?;
+    // merged-in work is listed under its merge commit instead of line by line
+    revwalk.simplify_first_parent()?;
 
     let mut commits: Vec<CommitInfo> = Vec::new();
     for oid in revwalk {
@@ -85,12 +221,124 @@ pub fn collect_commits(repo: &Repository, base_oid: Oid) -> Result<Vec<CommitInf
             .map(|s| String::from_utf8_lossy(s).to_string())
             .unwrap_or_else(|| "(no summary)".to_string());
         let short = oid.to_string()[..7].to_string();
+        let merged = if commit.parent_count() > 1 {
+            merged_commits(repo, &commit, merge_base)?
+        } else {
+            Vec::new()
+        };
         commits.push(CommitInfo {
             oid,
             short,
             summary,
+            source: source.name.clone(),
+            parents: commit.parent_count(),
+            merged,
         });
     }
     commits.reverse();
     Ok(commits)
 }
+
+/// Non-merge commits reachable from a merge's other parents but not from its first parent or `base`.
+fn merged_commits(repo: &Repository, merge: &git2::Commit, base: Oid) -> Result<Vec<Oid>> {
+    let mut revwalk = repo.revwalk()?;
+    for parent in merge.parent_ids().skip(1) {
+        revwalk.push(parent)?;
+    }
+    revwalk.hide(merge.parent_id(0)?)?;
+    revwalk.hide(base)?;
+    revwalk.set_sorting(Sort::TOPOLOGICAL | Sort::REVERSE)?;
+    let mut merged = Vec::new();
+    for oid in revwalk {
+        let oid = oid?;
+        if repo.find_commit(oid)?.parent_count() <= 1 {
+            merged.push(oid);
+        }
+    }
+    Ok(merged)
+}
+
+/// Stable patch-id of a commit's change against its first parent; `None` for merges.
+///
+/// With `subdir`, only the change below that directory counts, with paths relative to it.
+pub fn patch_id(repo: &Repository, oid: Oid, subdir: Option<&str>) -> Result<Option<Oid>> {
+    let commit = repo.find_commit(oid)?;
+    if commit.parent_count() > 1 {
+        return Ok(None);
+    }
+    let parent_tree = match commit.parent(0) {
+        Ok(parent) => subtree(repo, parent.tree()?, subdir)?,
+        Err(_) => None,
+    };
+    let tree = subtree(repo, commit.tree()?, subdir)?;
+    let diff = repo.diff_tree_to_tree(parent_tree.as_ref(), tree.as_ref(), None)?;
+    Ok(Some(diff.patchid(None)?))
+}
+
+/// The tree at `subdir` inside `tree` (the whole tree without one), or `None` if it is missing.
+pub fn subtree<'r>(
+    repo: &'r Repository,
+    tree: Tree<'r>,
+    subdir: Option<&str>,
+) -> Result<Option<Tree<'r>>> {
+    let Some(subdir) = subdir else {
+        return Ok(Some(tree));
+    };
+    match tree.get_path(Path::new(subdir)) {
+        Ok(entry) => Ok(entry.to_object(repo)?.into_tree().ok()),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Patch-ids of the commits reachable from `tip` but not from `base`.
+pub fn patch_ids_since(repo: &Repository, base: Oid, tip: Oid) -> Result<HashSet<Oid>> {
+    let mut revwalk = repo.revwalk()?;
+    revwalk.push(tip)?;
+    revwalk.hide(base)?;
+    let mut ids = HashSet::new();
+    for oid in revwalk {
+        if let Some(id) = patch_id(repo, oid?, None)? {
+            ids.insert(id);
+        }
+    }
+    Ok(ids)
+}
+
+/// Whether to sign written commits: `--sign`/`--no-sign`, else `commit.gpgSign`.
+///
+/// Signing itself is left to git (`gpg.format`, `user.signingKey`); this only rejects an SSH
+/// setup without a key up front instead of failing after the first pick.
+pub fn resolve_signing(repo: &Repository, sign: bool, no_sign: bool) -> Result<bool> {
+    let config = repo.config()?;
+    let sign = if sign || no_sign {
+        sign
+    } else {
+        config.get_bool("commit.gpgSign").unwrap_or(false)
+    };
+    if sign
+        && config.get_string("gpg.format").is_ok_and(|f| f == "ssh")
+        && config.get_string("user.signingKey").is_err()
+        && config.get_string("gpg.ssh.defaultKeyCommand").is_err()
+    {
+        return Err(anyhow!(
+            "gpg.format=ssh needs user.signingKey to sign extracted commits"
+        ));
+    }
+    Ok(sign)
+}
+
+/// True when the commit carries a gpg/ssh signature.
+pub fn is_signed(repo: &Repository, oid: Oid) -> bool {
+    repo.extract_signature(&oid, None).is_ok()
+}
+
+/// Names of all local branches.
+pub fn local_branches(repo: &Repository) -> Result<HashSet<String>> {
+    let mut names = HashSet::new();
+    for branch in repo.branches(Some(git2::BranchType::Local))? {
+        if let Some(name) = branch?.0.name()? {
+            names.insert(name.to_string());
+        }
+    }
+    Ok(names)
+}
@@ -0,0 +1,129 @@
+use crate::git_ops::SourceInfo;
+use crate::state::RecordedSource;
+use crate::worktree_apply::{cleanup_worktree, run_git};
+use anyhow::{Context, Result, anyhow};
+use git2::{Oid, Repository};
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+/// Commits created from imported patches stay reachable under this prefix until the session ends.
+pub(crate) const IMPORT_REF_PREFIX: &str = "refs/extract/import/";
+/// Import worktrees are `.git/<prefix><pid>-<id>`, so `--repair` can leave a running import alone.
+pub(crate) const IMPORT_WORKTREE_PREFIX: &str = "extract-import-";
+
+/// Imported patch series; the ref is deleted on drop unless a session took it over.
+pub struct ImportedPatches<'a> {
+    repo: &'a Repository,
+    refname: String,
+    keep: bool,
+}
+
+impl ImportedPatches<'_> {
+    /// Hand the ref over to the session, which removes it when it completes or is aborted.
+    pub fn keep(mut self) {
+        self.keep = true;
+    }
+}
+
+impl Drop for ImportedPatches<'_> {
+    fn drop(&mut self) {
+        if !self.keep
+            && let Ok(mut reference) = self.repo.find_reference(&self.refname)
+        {
+            let _ = reference.delete();
+        }
+    }
+}
+
+/// Apply an mbox file or a directory of `format-patch` files on top of `base_oid` with
+/// `git am -3` in a throwaway worktree, and describe the result as a commit source.
+pub fn import_mbox<'a>(
+    repo: &'a Repository,
+    path: &Path,
+    base_oid: Oid,
+) -> Result<(SourceInfo, ImportedPatches<'a>)> {
+    let repo_path = repo
+        .workdir()
+        .ok_or_else(|| anyhow!("repository has no working directory"))?;
+    // `git am` runs inside the temp worktree, so relative paths must be resolved here
+    let abs_path =
+        std::path::absolute(path).with_context(|| format!("resolving {}", path.display()))?;
+    let patches = patch_files(&abs_path)?;
+    let id = Uuid::new_v4().to_string();
+    let wt_path = repo.path().join(format!(
+        "{IMPORT_WORKTREE_PREFIX}{}-{id}",
+        std::process::id()
+    ));
+
+    run_git(
+        repo_path,
+        [
+            "worktree",
+            "add",
+            "--detach",
+            wt_path.to_str().unwrap(),
+            &base_oid.to_string(),
+        ],
+    )?;
+    let mut am: Vec<String> = vec!["am".into(), "-3".into()];
+    am.extend(patches.iter().map(|p| p.to_string_lossy().into_owned()));
+    let applied = run_git(&wt_path, &am).and_then(|_| {
+        let head = run_git(&wt_path, ["rev-parse", "HEAD"])?;
+        Oid::from_str(head.trim()).context("parse imported HEAD")
+    });
+    if applied.is_err() {
+        let _ = run_git(&wt_path, ["am", "--abort"]);
+    }
+    cleanup_worktree(repo_path, &wt_path)?;
+    let tip = applied.with_context(|| format!("applying patches from {}", path.display()))?;
+    if tip == base_oid {
+        return Err(anyhow!("no patches found in {}", path.display()));
+    }
+
+    let refname = format!("{IMPORT_REF_PREFIX}{id}");
+    repo.reference(&refname, tip, false, "git-extract import")?;
+    let source = SourceInfo {
+        name: path.display().to_string(),
+        refname: Some(refname.clone()),
+        tip,
+        start: base_oid,
+    };
+    let guard = ImportedPatches {
+        repo,
+        refname,
+        keep: false,
+    };
+    Ok((source, guard))
+}
+
+/// A single mbox file, or the files of a `format-patch` output directory in name order.
+fn patch_files(path: &Path) -> Result<Vec<PathBuf>> {
+    if !path.is_dir() {
+        if !path.exists() {
+            return Err(anyhow!("{} does not exist", path.display()));
+        }
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let mut files: Vec<PathBuf> = std::fs::read_dir(path)
+        .with_context(|| format!("listing {}", path.display()))?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<std::io::Result<_>>()?;
+    files.retain(|p| p.is_file() && p.extension().is_some_and(|e| e == "patch"));
+    files.sort();
+    if files.is_empty() {
+        return Err(anyhow!("no .patch files in {}", path.display()));
+    }
+    Ok(files)
+}
+
+/// Delete the import refs a finished or aborted session was keeping alive.
+pub(crate) fn remove_imports(repo: &Repository, sources: &[RecordedSource]) -> Result<()> {
+    for refname in sources.iter().filter_map(|s| s.refname.as_deref()) {
+        if refname.starts_with(IMPORT_REF_PREFIX)
+            && let Ok(mut reference) = repo.find_reference(refname)
+        {
+            reference.delete()?;
+        }
+    }
+    Ok(())
+}
@@ -1,5 +1,10 @@
 pub mod cli;
+pub mod config;
+pub mod conflict;
 pub mod git_ops;
+pub mod import;
+pub mod repair;
 pub mod routing;
 pub mod state;
+pub mod subdir;
 pub mod worktree_apply;
@@ -1,11 +1,14 @@
 use anyhow::{Context, Result};
-use clap::{CommandFactory, Parser};
+use clap::{CommandFactory, FromArgMatches};
 use cli::Args;
-use git_extract::{cli, git_ops, routing, state, worktree_apply};
+use git_extract::{cli, config, conflict, git_ops, import, repair, routing, state, worktree_apply};
 
 fn main() -> Result<()> {
-    let args = Args::parse();
+    let matches = Args::command().get_matches();
+    let mut args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
     let repo = git2::Repository::discover(".").context("not inside a git repository")?;
+    let extract_config = config::ExtractConfig::load(&repo)?;
+    extract_config.apply_to_args(&mut args, &matches);
 
     // Allow `git extract help` to show clap help
     if std::env::args().nth(1).as_deref() == Some("help") {
@@ -16,26 +19,68 @@ fn main() -> Result<()> {
 
     // resume/abort existing session
     if args.r#continue || args.abort {
-        let st = state::load_state(&repo).context("no extract session to continue/abort")?;
+        // lock before loading: loading may migrate the state file
+        let session_id = state::resolve_session(&repo, args.session.as_deref())
+            .context("no extract session to continue/abort")?;
+        let lock = state::lock_session(&repo, &session_id)?;
+        let st = state::load_state(&repo, &session_id)
+            .context("no extract session to continue/abort")?;
         if args.abort {
             worktree_apply::abort_session(&repo, &st, args.no_chdir_conflict)?;
-            state::remove_state(&repo)?;
+            state::remove_state(&repo, &st.session_id)?;
             println!("extract session aborted; temp worktree removed");
             return Ok(());
         }
+        for warning in state::verify_refs(&repo, &st)? {
+            eprintln!("warning: {warning}");
+        }
         let outcome =
             worktree_apply::resume_session(&repo, st, args.allow_dirty, args.no_chdir_conflict)?;
-        handle_outcome(&repo, outcome)?;
+        handle_outcome(&repo, &args, outcome, lock)?;
         return Ok(());
     }
 
-    state::ensure_no_state(&repo)?;
+    if args.repair {
+        repair::repair(&repo)?;
+        return Ok(());
+    }
+
+    let paused = state::list_sessions(&repo)?;
+    if !paused.is_empty() {
+        eprintln!(
+            "note: {} paused extract session(s) in this repository: {}",
+            paused.len(),
+            paused.join(", ")
+        );
+    }
     git_ops::ensure_clean(&repo, args.allow_dirty)?;
 
     let base_info = git_ops::detect_base(&repo, args.base.clone())?;
-    let commits = git_ops::collect_commits(&repo, base_info.base_oid)?;
+    // an imported series replaces HEAD as the default source
+    let mut sources = if args.from_mbox.is_some() && args.source.is_empty() && args.range.is_none()
+    {
+        Vec::new()
+    } else {
+        git_ops::resolve_sources(
+            &repo,
+            &args.source,
+            args.range.as_deref(),
+            base_info.base_oid,
+        )?
+    };
+    let imported = match &args.from_mbox {
+        Some(path) => {
+            let (source, patches) =
+                import::import_mbox(&repo, std::path::Path::new(path), base_info.base_oid)?;
+            sources.push(source);
+            Some(patches)
+        }
+        None => None,
+    };
+    let commits = git_ops::collect_commits(&repo, &sources)?;
 
-    let target_defs = routing::TargetDefs::from_args(&args);
+    let mut target_defs = routing::TargetDefs::from_args(&args, &extract_config)?;
+    target_defs.naming.taken = git_ops::local_branches(&repo)?;
     let draft_path = routing::render_routing_file(&target_defs, &commits)?;
 
     let routing_path = if let Some(path) = &args.routing_file {
@@ -47,30 +92,94 @@ fn main() -> Result<()> {
 
     let plan =
         routing::parse_routing_file(&routing_path, &commits, &target_defs, args.keep_current())?;
+    routing::validate_targets(&repo, &plan, &sources)?;
 
     if args.dry_run {
         routing::print_plan_summary(&plan);
         return Ok(());
     }
 
-    let outcome = worktree_apply::apply_plan(&repo, &plan, &base_info)?;
-    handle_outcome(&repo, outcome)?;
+    let options = worktree_apply::ApplyOptions {
+        keep_going: args.keep_going,
+        strategy: args.strategy.clone(),
+        strategy_options: args.strategy_option.clone(),
+        conflict_style: args.conflict_style.clone(),
+        empty: args.empty,
+        sign: git_ops::resolve_signing(&repo, args.sign, args.no_sign)?,
+        committer_date_is_author_date: args.committer_date_is_author_date,
+        reset_author: args.reset_author,
+        signoff: args.signoff,
+        record_origin: args.record_origin,
+        squash: args.squash_targets,
+        editor: Some(routing::resolve_editor(&args, &repo)),
+        force: args.force,
+    };
+    let session = worktree_apply::new_session(&repo, &plan, &base_info, &sources, &options)?;
+    if let Some(patches) = imported {
+        patches.keep();
+    }
+    let lock = state::lock_session(&repo, &session.session_id)?;
+    let outcome = worktree_apply::apply_plan(&repo, session)?;
+    handle_outcome(&repo, &args, outcome, lock)?;
 
     Ok(())
 }
 
-fn handle_outcome(repo: &git2::Repository, outcome: worktree_apply::ApplyOutcome) -> Result<()> {
-    match outcome {
-        worktree_apply::ApplyOutcome::Complete(summary) => {
-            state::remove_state(repo)?;
-            routing::print_apply_summary(&summary);
-        }
-        worktree_apply::ApplyOutcome::Conflict(st, msg) => {
-            state::save_state(repo, &st)?;
-            println!("Conflict encountered: {msg}");
+fn handle_outcome(
+    repo: &git2::Repository,
+    args: &Args,
+    mut outcome: worktree_apply::ApplyOutcome,
+    lock: state::SessionLock,
+) -> Result<()> {
+    loop {
+        let (st, msg) = match outcome {
+            worktree_apply::ApplyOutcome::Complete(summary) => {
+                state::remove_state(repo, &summary.session_id)?;
+                routing::print_apply_summary(&summary);
+                return Ok(());
+            }
+            worktree_apply::ApplyOutcome::Conflict(st, msg) => (st, msg),
+            worktree_apply::ApplyOutcome::Interrupted(st) => {
+                state::save_state(repo, &st)?;
+                eprintln!("interrupted; session {} saved", st.session_id);
+                eprintln!("Run: git extract --continue  (or --abort to cancel)");
+                drop(lock);
+                std::process::exit(130);
+            }
+        };
+        state::save_state(repo, &st)?;
+        println!("Session: {}", st.session_id);
+        println!("Conflict encountered: {msg}");
+        let worktrees: Vec<(usize, &str)> = if st.parked.is_empty() {
             println!("Resolve conflicts in worktree: {}", st.worktree_path);
+            vec![(st.current_branch_idx, st.worktree_path.as_str())]
+        } else {
+            println!("Branches needing resolution:");
+            for parked in &st.parked {
+                let branch = &st.branch_queues[parked.branch_idx].branch;
+                println!("  {branch}: {}", parked.worktree_path);
+            }
+            st.parked
+                .iter()
+                .map(|p| (p.branch_idx, p.worktree_path.as_str()))
+                .collect()
+        };
+        for (branch_idx, wt) in &worktrees {
+            let wt = std::path::Path::new(wt);
+            for line in conflict::explain_conflict(repo, &st, *branch_idx, wt).unwrap_or_default() {
+                println!("  {line}");
+            }
+        }
+
+        let mut resolved = false;
+        for (_, wt) in worktrees {
+            resolved |= conflict::run_conflict_tool(args.on_conflict, std::path::Path::new(wt))?;
+        }
+        if !resolved {
             println!("Then run: git extract --continue  (or --abort to cancel)");
+            return Ok(());
         }
+        outcome =
+            worktree_apply::resume_session(repo, *st, args.allow_dirty, args.no_chdir_conflict)?;
     }
-    Ok(())
 }
@@ -0,0 +1,261 @@
+use crate::import::{IMPORT_REF_PREFIX, IMPORT_WORKTREE_PREFIX};
+use crate::state::{
+    self, LEGACY_WORKTREE, SessionState, WORKTREE_PREFIX, process_alive, session_worktree_path,
+};
+use crate::worktree_apply::{
+    BACKUP_REF_PREFIX, cherry_pick_in_progress, cleanup_worktree, command_dir, run_git,
+    start_worktree, target_repo,
+};
+use anyhow::{Result, anyhow};
+use git2::{Oid, Repository};
+use std::path::{Path, PathBuf};
+
+const SESSION_ID_LEN: usize = 36;
+
+/// Bring extract sessions back to a consistent state after a crash, kill or manual cleanup.
+///
+/// Stale locks are dropped, sessions whose worktrees vanished get them recreated, and
+/// worktrees and backup refs that belong to no session are removed.
+pub fn repair(repo: &Repository) -> Result<()> {
+    let repo_path = repo
+        .workdir()
+        .ok_or_else(|| anyhow!("repository has no working directory"))?;
+    let mut report: Vec<String> = Vec::new();
+
+    if let Some(id) = state::legacy_session_id(repo)
+        && let Ok(_lock) = state::lock_session(repo, &id)
+        && state::migrate_legacy_state(repo)?.is_some()
+    {
+        report.push(format!("migrated legacy state file to session {id}"));
+    }
+    let sessions = state::list_sessions(repo)?;
+
+    // import refs still used by a session, including ones busy in another process, and the
+    // forks whose worktrees sessions register there
+    let mut imports: Vec<String> = Vec::new();
+    let mut owners: Vec<PathBuf> = vec![repo_path.to_path_buf()];
+    for id in &sessions {
+        if let Ok((st, _)) = state::read_state(repo, id) {
+            imports.extend(st.sources.into_iter().filter_map(|s| s.refname));
+            owners.extend(
+                st.branch_queues
+                    .into_iter()
+                    .filter_map(|q| q.repo.map(PathBuf::from)),
+            );
+        }
+    }
+    owners.sort();
+    owners.dedup();
+    // forget registrations of worktree directories that were deleted by hand
+    run_git(repo_path, ["worktree", "prune"])?;
+    for fork in &owners[..] {
+        let _ = run_git(fork, ["worktree", "prune"]);
+    }
+
+    for id in &sessions {
+        if state::clear_stale_lock(repo, id)? {
+            report.push(format!("removed stale lock of session {id}"));
+        }
+        let Ok(_lock) = state::lock_session(repo, id) else {
+            report.push(format!(
+                "session {id} is in use by another process; skipped"
+            ));
+            continue;
+        };
+        let mut st = state::load_state(repo, id)?;
+        let fixes = reconcile_session(repo, &mut st)?;
+        if !fixes.is_empty() {
+            state::save_state(repo, &st)?;
+            report.extend(fixes);
+        }
+    }
+
+    for wt_path in extract_worktrees(repo, &owners)? {
+        let name = wt_path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        // an import worktree lives only while its `--from-mbox` run applies the patches
+        let importer = name
+            .strip_prefix(IMPORT_WORKTREE_PREFIX)
+            .and_then(|rest| rest.split('-').next())
+            .and_then(|pid| pid.parse::<i32>().ok());
+        if importer.is_some_and(process_alive) {
+            continue;
+        }
+        let session = name
+            .strip_prefix(WORKTREE_PREFIX)
+            .filter(|rest| rest.len() >= SESSION_ID_LEN)
+            .map(|rest| &rest[..SESSION_ID_LEN]);
+        if session.is_some_and(|id| sessions.iter().any(|s| s == id)) {
+            continue;
+        }
+        if cherry_pick_in_progress(&wt_path).unwrap_or(false) {
+            let _ = run_git(&wt_path, ["cherry-pick", "--abort"]);
+        }
+        let owner = worktree_owner(&wt_path).unwrap_or_else(|| repo_path.to_path_buf());
+        cleanup_worktree(&owner, &wt_path)?;
+        report.push(format!("removed orphaned worktree {}", wt_path.display()));
+    }
+
+    for reference in repo.references_glob(&format!("{BACKUP_REF_PREFIX}*"))? {
+        let mut reference = reference?;
+        let name = reference.name().unwrap_or_default().to_string();
+        let owner = name
+            .strip_prefix(BACKUP_REF_PREFIX)
+            .and_then(|rest| rest.split('/').next())
+            .unwrap_or_default();
+        if sessions.iter().any(|s| s == owner) {
+            continue;
+        }
+        let old = reference
+            .target()
+            .map(|oid| oid.to_string())
+            .unwrap_or_default();
+        reference.delete()?;
+        report.push(format!("deleted dangling backup ref {name} (was {old})"));
+    }
+
+    for reference in repo.references_glob(&format!("{IMPORT_REF_PREFIX}*"))? {
+        let mut reference = reference?;
+        let name = reference.name().unwrap_or_default().to_string();
+        if imports.contains(&name) {
+            continue;
+        }
+        reference.delete()?;
+        report.push(format!("deleted dangling import ref {name}"));
+    }
+
+    if report.is_empty() {
+        println!("nothing to repair");
+    }
+    for line in report {
+        println!("{line}");
+    }
+    Ok(())
+}
+
+/// Match the worktrees on disk with what the state file expects.
+fn reconcile_session(repo: &Repository, st: &mut SessionState) -> Result<Vec<String>> {
+    let mut fixes = Vec::new();
+    let base_oid = Oid::from_str(&st.base_oid)?;
+    let wt_path = session_worktree_path(repo, &st.session_id);
+    let current = st.current_branch_idx;
+
+    if current < st.branch_queues.len() {
+        if st.in_conflict && !wt_path.exists() {
+            rebuild_branch(repo, st, current, base_oid, &wt_path)?;
+            st.worktree_path = wt_path.to_string_lossy().into_owned();
+            fixes.push(format!(
+                "recreated worktree for {}; its commits will be re-applied on --continue",
+                st.branch_queues[current].branch
+            ));
+        } else if !st.in_conflict && wt_path.exists() {
+            // killed mid-branch: --continue restarts this branch from its checkpoint
+            let stale_pick = cherry_pick_in_progress(&wt_path).unwrap_or(false);
+            // registered in the repository the branch lives in, which may be a fork
+            let owner = target_repo(repo, &st.branch_queues[current])?;
+            cleanup_worktree(command_dir(&owner), &wt_path)?;
+            fixes.push(format!(
+                "removed half-applied worktree for {}{}",
+                st.branch_queues[current].branch,
+                if stale_pick {
+                    " (discarded stale cherry-pick)"
+                } else {
+                    ""
+                }
+            ));
+        }
+    }
+
+    for i in 0..st.parked.len() {
+        let parked_wt = PathBuf::from(&st.parked[i].worktree_path);
+        if parked_wt.exists() {
+            continue;
+        }
+        let idx = st.parked[i].branch_idx;
+        rebuild_branch(repo, st, idx, base_oid, &parked_wt)?;
+        fixes.push(format!(
+            "recreated parked worktree for {}",
+            st.branch_queues[idx].branch
+        ));
+    }
+    Ok(fixes)
+}
+
+/// Restart one branch from its routed commits in a fresh worktree; its ref is only
+/// moved once all of them apply, so nothing already written is lost.
+fn rebuild_branch(
+    repo: &Repository,
+    st: &mut SessionState,
+    idx: usize,
+    base_oid: Oid,
+    wt_path: &Path,
+) -> Result<()> {
+    let branch = st.branch_queues[idx].branch.clone();
+    let commits = st
+        .routes
+        .iter()
+        .filter(|r| r.dest == branch)
+        .map(|r| r.sha.clone())
+        .collect();
+    let session_id = st.session_id.clone();
+    let sign = st.options.sign;
+    let queue = &mut st.branch_queues[idx];
+    queue.commits = commits;
+    queue.applied = 0;
+    queue.skipped = 0;
+    queue.unsigned = 0;
+    queue.last_written = None;
+    queue.pick_base = None;
+    queue.interrupted = true;
+    start_worktree(repo, &session_id, queue, base_oid, wt_path, sign)
+}
+
+/// Extract and import worktrees: those registered in the repository or one of the known forks,
+/// plus directories in `.git` whose registration sits in a fork no session knows anymore.
+fn extract_worktrees(repo: &Repository, owners: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let is_ours = |p: &Path| {
+        p.file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .is_some_and(|n| {
+                n == LEGACY_WORKTREE
+                    || n.starts_with(WORKTREE_PREFIX)
+                    || n.starts_with(IMPORT_WORKTREE_PREFIX)
+            })
+    };
+    let mut found: Vec<PathBuf> = Vec::new();
+    for owner in owners {
+        let Ok(listing) = run_git(owner, ["worktree", "list", "--porcelain"]) else {
+            continue;
+        };
+        found.extend(
+            listing
+                .lines()
+                .filter_map(|line| line.strip_prefix("worktree "))
+                .map(PathBuf::from),
+        );
+    }
+    for entry in std::fs::read_dir(repo.path())? {
+        let path = entry?.path();
+        if path.is_dir() {
+            found.push(path);
+        }
+    }
+    let mut worktrees: Vec<PathBuf> = found
+        .into_iter()
+        .filter(|p| is_ours(p) && p.exists())
+        .filter_map(|p| p.canonicalize().ok())
+        .collect();
+    worktrees.sort();
+    worktrees.dedup();
+    Ok(worktrees)
+}
+
+/// Directory to run `git worktree` in for the repository `wt_path` is registered in.
+fn worktree_owner(wt_path: &Path) -> Option<PathBuf> {
+    let common = run_git(wt_path, ["rev-parse", "--git-common-dir"]).ok()?;
+    let common = wt_path.join(common.trim());
+    let owner = Repository::open(common).ok()?;
+    Some(command_dir(&owner).to_path_buf())
+}
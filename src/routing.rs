@@ -1,8 +1,11 @@
 use crate::cli::Args;
-use crate::git_ops::CommitInfo;
+use crate::config::ExtractConfig;
+use crate::git_ops::{CommitInfo, SourceInfo};
+use crate::worktree_apply::SquashMode;
 use anyhow::{Context, Result, anyhow};
 use git2::Oid;
 use git2::Repository;
+use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::io::Write;
@@ -18,8 +21,22 @@ pub struct TargetDef {
 #[derive(Debug, Clone, Default)]
 pub struct TargetDefs {
     pub targets: Vec<TargetDef>,
+    pub naming: BranchNaming,
 }
 
+/// How branches named in the routing file are completed: `extract.branchPrefix` for short
+/// names on `target` lines, and `extract.branchTemplate` for `new` destinations.
+#[derive(Debug, Clone, Default)]
+pub struct BranchNaming {
+    pub prefix: Option<String>,
+    pub template: Option<String>,
+    pub user: Option<String>,
+    /// Existing local branches; generated names avoid them.
+    pub taken: HashSet<String>,
+}
+
+pub const DEFAULT_BRANCH_TEMPLATE: &str = "{slug}";
+
 #[derive(Debug, Clone)]
 pub enum Dest {
     Branch(String),
@@ -27,19 +44,148 @@ pub enum Dest {
     Drop,
 }
 
+/// How a routed commit is replayed onto its destination.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Pick {
+    Commit,
+    /// Merge picked as one commit against the given parent (`<dest>^<n>`).
+    Mainline(u32),
+    /// Merge skipped; the commits it brought in are picked one by one.
+    Flatten(Vec<Oid>),
+    /// `fixup!`/`squash!` commit folded into the commit before it on the same destination.
+    Fold(Fold),
+}
+
+/// How a folded commit joins the previous one: `fixup` keeps only that commit's message,
+/// `squash` appends its own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Fold {
+    Fixup,
+    Squash,
+}
+
+impl Fold {
+    fn token(self) -> &'static str {
+        match self {
+            Fold::Fixup => "fixup",
+            Fold::Squash => "squash",
+        }
+    }
+
+    fn from_token(token: &str) -> Option<Fold> {
+        match token {
+            "fixup" => Some(Fold::Fixup),
+            "squash" => Some(Fold::Squash),
+            _ => None,
+        }
+    }
+
+    /// The fold a `fixup! <subject>`/`squash! <subject>` commit asks for, with the subject it
+    /// refers to (nested prefixes stripped).
+    fn from_subject(subject: &str) -> Option<(Fold, &str)> {
+        let (fold, mut rest) = if let Some(rest) = subject.strip_prefix("fixup! ") {
+            (Fold::Fixup, rest)
+        } else {
+            (Fold::Squash, subject.strip_prefix("squash! ")?)
+        };
+        while let Some(inner) = rest
+            .strip_prefix("fixup! ")
+            .or_else(|| rest.strip_prefix("squash! "))
+        {
+            rest = inner;
+        }
+        Some((fold, rest))
+    }
+}
+
+/// Listing order with `fixup!`/`squash!` commits moved right below the commit they refer to
+/// (same source, listed earlier; matched by subject, sha prefix, then subject prefix).
+fn autosquash_order(commits: &[CommitInfo]) -> Vec<(&CommitInfo, Option<Fold>)> {
+    let mut followers: Vec<Vec<(usize, Fold)>> = vec![Vec::new(); commits.len()];
+    let mut moved = vec![false; commits.len()];
+    for (i, commit) in commits.iter().enumerate() {
+        let Some((fold, target)) = Fold::from_subject(&commit.summary) else {
+            continue;
+        };
+        if commit.is_merge() || target.is_empty() {
+            continue;
+        }
+        let candidates = || {
+            commits[..i]
+                .iter()
+                .enumerate()
+                .filter(|(j, c)| !moved[*j] && c.source == commit.source && !c.is_merge())
+        };
+        let found = candidates()
+            .find(|(_, c)| c.summary == target)
+            .or_else(|| {
+                candidates()
+                    .find(|(_, c)| target.len() >= 4 && c.oid.to_string().starts_with(target))
+            })
+            .or_else(|| candidates().find(|(_, c)| c.summary.starts_with(target)));
+        if let Some((j, _)) = found {
+            followers[j].push((i, fold));
+            moved[i] = true;
+        }
+    }
+    let mut order = Vec::with_capacity(commits.len());
+    for (i, commit) in commits.iter().enumerate() {
+        if moved[i] {
+            continue;
+        }
+        order.push((commit, None));
+        order.extend(
+            followers[i]
+                .iter()
+                .map(|&(k, fold)| (&commits[k], Some(fold))),
+        );
+    }
+    order
+}
+
 #[derive(Debug, Clone)]
 pub struct Assignment {
     pub oid: Oid,
     pub dest: Dest,
+    pub pick: Pick,
 }
 
+/// Per-target settings given as `key=value` tokens after the branch on a `target` line.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TargetOptions {
+    #[serde(default)]
+    pub strategy: Option<String>,
+    #[serde(default)]
+    pub strategy_options: Vec<String>,
+    #[serde(default)]
+    pub conflict_style: Option<String>,
+    /// Rewrite picks so paths are relative to this directory, dropping changes outside it.
+    #[serde(default)]
+    pub subdir: Option<String>,
+    /// Text put in front of the subject of every commit written to the target.
+    #[serde(default)]
+    pub prefix: Option<String>,
+    /// `Key: value` trailers added to every commit written to the target.
+    #[serde(default)]
+    pub trailers: Vec<String>,
+    /// Collapse the commits written to the target into one.
+    #[serde(default)]
+    pub squash: Option<SquashMode>,
+}
+
+pub const CONFLICT_STYLES: [&str; 3] = ["merge", "diff3", "zdiff3"];
+
 #[derive(Debug, Clone)]
 pub struct RoutingPlan {
     pub assignments: Vec<Assignment>,
+    pub target_options: HashMap<String, TargetOptions>,
 }
 
 impl TargetDefs {
-    pub fn from_args(args: &Args) -> Self {
+    /// Targets from `--targets` and positional arguments; `@<name>` expands the
+    /// `extract.group.<name>.targets` group and `extract.branchPrefix` is applied to each.
+    pub fn from_args(args: &Args, config: &ExtractConfig) -> Result<Self> {
         let mut combined: Vec<String> = Vec::new();
         let mut seen: HashSet<String> = HashSet::new();
 
@@ -48,8 +194,17 @@ impl TargetDefs {
             if trimmed.is_empty() {
                 continue;
             }
-            if seen.insert(trimmed.to_string()) {
-                combined.push(trimmed.to_string());
+            let names = match trimmed.strip_prefix('@') {
+                Some(group) => config.groups.get(group).cloned().ok_or_else(|| {
+                    anyhow!("unknown target group {group} (extract.group.{group}.targets)")
+                })?,
+                None => vec![trimmed.to_string()],
+            };
+            for name in names {
+                let name = with_branch_prefix(&name, config.branch_prefix.as_deref());
+                if seen.insert(name.clone()) {
+                    combined.push(name);
+                }
             }
         }
 
@@ -62,7 +217,112 @@ impl TargetDefs {
             })
             .collect();
 
-        TargetDefs { targets }
+        Ok(TargetDefs {
+            targets,
+            naming: BranchNaming {
+                prefix: config.branch_prefix.clone(),
+                template: config.branch_template.clone(),
+                user: config.user.clone(),
+                taken: HashSet::new(),
+            },
+        })
+    }
+}
+
+impl BranchNaming {
+    /// `target` line branch: short names (no `/`) get the prefix.
+    fn target_branch(&self, name: &str) -> String {
+        let branch = name.rsplit_once(':').map_or(name, |(_, branch)| branch);
+        if branch.contains('/') {
+            name.to_string()
+        } else {
+            with_branch_prefix(name, self.prefix.as_deref())
+        }
+    }
+
+    /// Branch for a `new` destination: the template filled from the commit, prefixed, with
+    /// `-2`, `-3`, ... appended while the name is taken or already used in the plan.
+    fn new_branch(&self, commit: &CommitInfo, used: &HashSet<String>) -> Result<String> {
+        let template = self.template.as_deref().unwrap_or(DEFAULT_BRANCH_TEMPLATE);
+        let mut name = String::new();
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            name.push_str(&rest[..start]);
+            let end = rest[start..]
+                .find('}')
+                .map(|end| start + end)
+                .ok_or_else(|| anyhow!("unterminated placeholder in branch template {template}"))?;
+            match &rest[start + 1..end] {
+                "slug" => {
+                    let slug = slugify(&commit.summary);
+                    name.push_str(if slug.is_empty() {
+                        &commit.short
+                    } else {
+                        &slug
+                    });
+                }
+                "sha" => name.push_str(&commit.short),
+                "user" => name.push_str(self.user.as_deref().ok_or_else(|| {
+                    anyhow!("branch template {template} needs user.email or user.name for {{user}}")
+                })?),
+                other => {
+                    return Err(anyhow!(
+                        "unknown placeholder {{{other}}} in branch template {template}; expected {{user}}, {{slug}} or {{sha}}"
+                    ));
+                }
+            }
+            rest = &rest[end + 1..];
+        }
+        name.push_str(rest);
+        let name = with_branch_prefix(&name, self.prefix.as_deref());
+        let mut candidate = name.clone();
+        let mut n = 2;
+        while self.taken.contains(&candidate) || used.contains(&candidate) {
+            candidate = format!("{name}-{n}");
+            n += 1;
+        }
+        Ok(candidate)
+    }
+}
+
+/// `Add OAuth login!` -> `add-oauth-login`: lowercase ASCII letters and digits, anything else
+/// collapsed into single dashes, cut back to a whole word after 50 characters.
+pub fn slugify(text: &str) -> String {
+    let mut slug = String::new();
+    for c in text.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    if slug.len() > 50 {
+        slug.truncate(50);
+        if let Some(cut) = slug.rfind('-') {
+            slug.truncate(cut);
+        }
+    }
+    slug.trim_end_matches('-').to_string()
+}
+
+/// `feature` -> `<prefix>feature`, also for the branch of a `<path>:<branch>` target; names that
+/// already carry the prefix are left alone.
+fn with_branch_prefix(name: &str, prefix: Option<&str>) -> String {
+    let Some(prefix) = prefix else {
+        return name.to_string();
+    };
+    let (repo, branch) = match name.rsplit_once(':') {
+        Some((repo, branch)) => (Some(repo), branch),
+        None => (None, name),
+    };
+    let branch = if branch.starts_with(prefix) {
+        branch.to_string()
+    } else {
+        format!("{prefix}{branch}")
+    };
+    match repo {
+        Some(repo) => format!("{repo}:{branch}"),
+        None => branch,
     }
 }
 
@@ -78,8 +338,27 @@ pub fn render_routing_file(targets: &TargetDefs, commits: &[CommitInfo]) -> Resu
     if !targets.targets.is_empty() {
         writeln!(file)?;
     }
-    for commit in commits {
-        writeln!(file, "current {} {}", commit.short, commit.summary)?;
+    // group by origin only when several sources were gathered
+    let grouped = commits.iter().any(|c| c.source != commits[0].source);
+    let mut current_source: Option<&str> = None;
+    for (commit, fold) in autosquash_order(commits) {
+        if grouped && current_source != Some(commit.source.as_str()) {
+            if current_source.is_some() {
+                writeln!(file)?;
+            }
+            writeln!(file, "# from {}", commit.source)?;
+            current_source = Some(&commit.source);
+        }
+        if commit.is_merge() {
+            writeln!(
+                file,
+                "# merge {}: <dest> picks the {} commit(s) it brought in, <dest>^<n> picks the merge against parent n, drop skips it",
+                commit.short,
+                commit.merged.len()
+            )?;
+        }
+        let dest = fold.map_or("current", Fold::token);
+        writeln!(file, "{dest} {} {}", commit.short, commit.summary)?;
     }
     Ok(path)
 }
@@ -97,6 +376,33 @@ pub fn launch_editor(args: &Args, repo: &Repository, path: &Path) -> Result<()>
     Ok(())
 }
 
+pub fn resolve_editor(args: &Args, repo: &Repository) -> String {
+    if let Some(e) = &args.editor {
+        return e.clone();
+    }
+    for var in ["GIT_SEQUENCE_EDITOR", "GIT_EDITOR"] {
+        if let Ok(v) = std::env::var(var)
+            && !v.trim().is_empty()
+        {
+            return v;
+        }
+    }
+    if let Ok(cfg) = repo.config()
+        && let Ok(v) = cfg.get_string("core.editor")
+        && !v.trim().is_empty()
+    {
+        return v;
+    }
+    for var in ["VISUAL", "EDITOR"] {
+        if let Ok(v) = std::env::var(var)
+            && !v.trim().is_empty()
+        {
+            return v;
+        }
+    }
+    "vi".to_string()
+}
+
 pub fn parse_routing_file(
     path: &Path,
     commits: &[CommitInfo],
@@ -110,8 +416,11 @@ pub fn parse_routing_file(
         alias_map.insert(t.alias, t.branch.clone());
     }
 
+    let mut target_options: HashMap<String, TargetOptions> = HashMap::new();
     let mut assignments: Vec<Assignment> = Vec::new();
     let mut seen_oids: HashSet<git2::Oid> = HashSet::new();
+    // destination of the last regular line; `fixup`/`squash` lines follow it
+    let mut last_dest: Option<Dest> = None;
 
     let commit_map: HashMap<String, Oid> =
         commits.iter().map(|c| (c.short.clone(), c.oid)).collect();
@@ -122,7 +431,8 @@ pub fn parse_routing_file(
             continue;
         }
         if trimmed.starts_with("target ") {
-            let mut parts = trimmed.split_whitespace();
+            let tokens = split_quoted(trimmed)?;
+            let mut parts = tokens.iter().map(String::as_str);
             let _ = parts.next(); // target
             let alias = parts
                 .next()
@@ -132,7 +442,10 @@ pub fn parse_routing_file(
             let branch = parts
                 .next()
                 .ok_or_else(|| anyhow!("target line missing branch"))?;
-            alias_map.insert(alias, branch.to_string());
+            let options = parse_target_options(parts)?;
+            let branch = targets.naming.target_branch(branch);
+            alias_map.insert(alias, branch.clone());
+            target_options.insert(branch, options);
             continue;
         }
 
@@ -157,8 +470,56 @@ pub fn parse_routing_file(
             return Err(anyhow!("duplicate assignment for commit {sha_token}"));
         }
 
-        let dest = parse_dest(dest_token, &alias_map, keep_current)?;
-        assignments.push(Assignment { oid, dest });
+        let commit = commits
+            .iter()
+            .find(|c| c.oid == oid)
+            .expect("resolved oid is listed");
+        if let Some(fold) = Fold::from_token(dest_token) {
+            if commit.is_merge() {
+                return Err(anyhow!("{sha_token} is a merge and cannot be folded"));
+            }
+            let dest = last_dest.clone().ok_or_else(|| {
+                anyhow!("{dest_token} {sha_token} has no commit above it to fold into")
+            })?;
+            assignments.push(Assignment {
+                oid,
+                dest,
+                pick: Pick::Fold(fold),
+            });
+            continue;
+        }
+        let (dest_token, mainline) = split_mainline(dest_token)?;
+        let dest = if dest_token == "new" {
+            let used: HashSet<String> = alias_map
+                .values()
+                .cloned()
+                .chain(assignments.iter().filter_map(|a| match &a.dest {
+                    Dest::Branch(b) => Some(b.clone()),
+                    _ => None,
+                }))
+                .collect();
+            Dest::Branch(targets.naming.new_branch(commit, &used)?)
+        } else {
+            parse_dest(dest_token, &alias_map, keep_current)?
+        };
+        last_dest = Some(dest.clone());
+        let pick = match mainline {
+            Some(_) if !commit.is_merge() => {
+                return Err(anyhow!(
+                    "{sha_token} is not a merge; ^<n> only applies to merge commits"
+                ));
+            }
+            Some(n) if n == 0 || n as usize > commit.parents => {
+                return Err(anyhow!(
+                    "{sha_token} has {} parents; mainline {n} is out of range",
+                    commit.parents
+                ));
+            }
+            Some(n) => Pick::Mainline(n),
+            None if commit.is_merge() => Pick::Flatten(commit.merged.clone()),
+            None => Pick::Commit,
+        };
+        assignments.push(Assignment { oid, dest, pick });
     }
 
     // Ensure every commit is assigned
@@ -166,10 +527,175 @@ pub fn parse_routing_file(
         return Err(anyhow!("every listed commit must be assigned"));
     }
 
-    Ok(RoutingPlan { assignments })
+    Ok(RoutingPlan {
+        assignments,
+        target_options,
+    })
+}
+
+fn parse_target_options<'a>(tokens: impl Iterator<Item = &'a str>) -> Result<TargetOptions> {
+    let mut options = TargetOptions::default();
+    for token in tokens {
+        if token == "squash" {
+            options.squash = Some(SquashMode::Plain);
+            continue;
+        }
+        let (key, value) = token
+            .split_once('=')
+            .ok_or_else(|| anyhow!("invalid target option {token}; expected key=value"))?;
+        match key {
+            "strategy" => options.strategy = Some(value.to_string()),
+            "strategy-option" => options.strategy_options.push(value.to_string()),
+            "conflict-style" => {
+                if !CONFLICT_STYLES.contains(&value) {
+                    return Err(anyhow!(
+                        "invalid conflict-style {value}; expected one of {}",
+                        CONFLICT_STYLES.join(", ")
+                    ));
+                }
+                options.conflict_style = Some(value.to_string());
+            }
+            "subdir" => {
+                let dir = value.trim_matches('/');
+                if dir.is_empty() || dir.split('/').any(|part| part == ".." || part == ".") {
+                    return Err(anyhow!(
+                        "invalid subdir {value}; expected a path inside the repository"
+                    ));
+                }
+                options.subdir = Some(dir.to_string());
+            }
+            "squash" => {
+                options.squash = Some(match value {
+                    "edit" => SquashMode::Edit,
+                    "plain" => SquashMode::Plain,
+                    _ => {
+                        return Err(anyhow!(
+                            "invalid squash={value}; expected squash or squash=edit"
+                        ));
+                    }
+                })
+            }
+            "prefix" => options.prefix = Some(value.to_string()),
+            "trailer" => {
+                if !value.split_once(':').is_some_and(|(k, v)| {
+                    !k.trim().is_empty() && !k.contains(' ') && !v.trim().is_empty()
+                }) {
+                    return Err(anyhow!(
+                        "invalid trailer {value:?}; expected \"Key: value\""
+                    ));
+                }
+                options.trailers.push(value.to_string());
+            }
+            _ => return Err(anyhow!("unknown target option {key}")),
+        }
+    }
+    Ok(options)
+}
+
+/// Check every target branch of the plan before anything is written: git refname rules, not
+/// the source branch, and no `feature` vs `feature/x` clash with existing branches of the
+/// target repository or with other targets. All problems are reported together.
+pub fn validate_targets(
+    repo: &Repository,
+    plan: &RoutingPlan,
+    sources: &[SourceInfo],
+) -> Result<()> {
+    let mut branches: Vec<&str> = Vec::new();
+    for a in &plan.assignments {
+        if let Dest::Branch(b) = &a.dest
+            && !branches.contains(&b.as_str())
+        {
+            branches.push(b);
+        }
+    }
+    let workdir = repo.workdir().unwrap_or(repo.path());
+    let mut problems = Vec::new();
+    for target in &branches {
+        let (fork, branch) = match target.rsplit_once(':') {
+            Some((path, branch)) => (Some(path), branch),
+            None => (None, *target),
+        };
+        let refname = format!("refs/heads/{branch}");
+        if branch == "HEAD" || branch.starts_with('-') || !git2::Reference::is_valid_name(&refname)
+        {
+            problems.push(format!("{target}: not a valid branch name"));
+            continue;
+        }
+        if fork.is_none()
+            && sources
+                .iter()
+                .any(|s| s.refname.as_deref() == Some(refname.as_str()))
+        {
+            problems.push(format!(
+                "{target}: is a source branch; route its commits with current instead"
+            ));
+        }
+        // a fork that cannot be opened is reported when the session starts
+        let target_repo = match fork {
+            Some(path) => Repository::open(workdir.join(path)).ok(),
+            None => Repository::open(repo.path()).ok(),
+        };
+        if let Some(target_repo) = &target_repo {
+            problems.extend(
+                ref_clashes(target_repo, &refname)
+                    .into_iter()
+                    .map(|existing| format!("{target}: clashes with existing {existing}")),
+            );
+        }
+        for other in &branches {
+            let same_repo = other.rsplit_once(':').map(|(p, _)| p) == fork;
+            let other_branch = other.rsplit_once(':').map_or(*other, |(_, b)| b);
+            if same_repo && other_branch.starts_with(&format!("{branch}/")) {
+                problems.push(format!("{target}: clashes with target {other}"));
+            }
+        }
+    }
+    if problems.is_empty() {
+        return Ok(());
+    }
+    Err(anyhow!(
+        "invalid target branch(es) in the routing file:\n  {}",
+        problems.join("\n  ")
+    ))
+}
+
+/// Existing refs that keep `refname` from being created: a ref at one of its parent paths
+/// (`refs/heads/feature` for `refs/heads/feature/x`) or refs below it.
+fn ref_clashes(repo: &Repository, refname: &str) -> Vec<String> {
+    let mut clashes = Vec::new();
+    let mut parent = refname;
+    while let Some((dir, _)) = parent.rsplit_once('/') {
+        if dir == "refs/heads" {
+            break;
+        }
+        if repo.find_reference(dir).is_ok() {
+            clashes.push(dir.to_string());
+        }
+        parent = dir;
+    }
+    if let Ok(refs) = repo.references_glob(&format!("{refname}/*")) {
+        clashes.extend(refs.flatten().filter_map(|r| r.name().map(str::to_string)));
+    }
+    clashes
+}
+
+/// Split a `<dest>^<n>` token into the destination and the mainline parent.
+fn split_mainline(token: &str) -> Result<(&str, Option<u32>)> {
+    match token.rsplit_once('^') {
+        Some((dest, n)) => {
+            let n = n
+                .parse::<u32>()
+                .with_context(|| format!("invalid mainline parent in {token}"))?;
+            Ok((dest, Some(n)))
+        }
+        None => Ok((token, None)),
+    }
 }
 
 fn parse_dest(token: &str, alias_map: &HashMap<u32, String>, keep_current: bool) -> Result<Dest> {
+    if token == "drop" {
+        return Ok(Dest::Drop);
+    }
     if token == "current" {
         return if keep_current {
             Ok(Dest::Current)
@@ -187,6 +713,51 @@ fn parse_dest(token: &str, alias_map: &HashMap<u32, String>, keep_current: bool)
     Ok(Dest::Branch(token.to_string()))
 }
 
+/// Split a target line on whitespace; double quotes group text with spaces (`prefix="[X] "`)
+/// and `\"`/`\\` escape inside them.
+fn split_quoted(line: &str) -> Result<Vec<String>> {
+    let mut tokens = Vec::new();
+    let mut token = String::new();
+    let mut in_token = false;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                in_token = true;
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(escaped @ ('"' | '\\')) => token.push(escaped),
+                            Some(other) => {
+                                token.push('\\');
+                                token.push(other);
+                            }
+                            None => return Err(anyhow!("unterminated quote in: {line}")),
+                        },
+                        Some(other) => token.push(other),
+                        None => return Err(anyhow!("unterminated quote in: {line}")),
+                    }
+                }
+            }
+            c if c.is_whitespace() => {
+                if in_token {
+                    tokens.push(std::mem::take(&mut token));
+                    in_token = false;
+                }
+            }
+            c => {
+                in_token = true;
+                token.push(c);
+            }
+        }
+    }
+    if in_token {
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
 fn resolve_full_sha(commits: &[CommitInfo], token: &str) -> Option<Oid> {
     if token.len() < 7 {
         return None;
@@ -218,8 +789,12 @@ pub fn print_plan_summary(plan: &RoutingPlan) {
 
 #[derive(Debug, Clone)]
 pub struct ApplySummary {
+    pub session_id: String,
     pub created_branches: Vec<String>,
     pub commits_per_branch: HashMap<String, usize>,
+    pub skipped_per_branch: HashMap<String, usize>,
+    /// Branches where signed source commits were copied without a signature.
+    pub unsigned_per_branch: HashMap<String, usize>,
 }
 
 pub fn print_apply_summary(summary: &ApplySummary) {
@@ -228,7 +803,17 @@ pub fn print_apply_summary(summary: &ApplySummary) {
         println!("  created: {}", summary.created_branches.join(", "));
     }
     for (branch, count) in &summary.commits_per_branch {
-        println!("  {branch}: {count} commits");
+        match summary.skipped_per_branch.get(branch) {
+            Some(&skipped) if skipped > 0 => {
+                println!("  {branch}: {count} commits ({skipped} skipped)")
+            }
+            _ => println!("  {branch}: {count} commits"),
+        }
+    }
+    for (branch, unsigned) in &summary.unsigned_per_branch {
+        eprintln!(
+            "warning: {branch}: {unsigned} signed commit(s) were copied without a signature; rerun with --sign or set commit.gpgSign"
+        );
     }
 }
 
@@ -245,6 +830,9 @@ mod tests {
             oid,
             short: hex[..7].to_string(),
             summary: format!("Commit {idx}"),
+            source: "work".into(),
+            parents: 1,
+            merged: Vec::new(),
         }
     }
 
@@ -256,6 +844,7 @@ mod tests {
                 alias: 1,
                 branch: "feature".into(),
             }],
+            ..Default::default()
         };
 
         let path = std::env::temp_dir().join("routing-test.txt");
@@ -271,39 +860,202 @@ mod tests {
         assert!(matches!(plan.assignments[1].dest, Dest::Current));
     }
 
+    #[test]
+    fn parse_target_line_options() {
+        let commits = vec![mk_commit(1)];
+        let targets = TargetDefs::default();
+        let path = std::env::temp_dir().join("routing-test-options.txt");
+        let content = format!(
+            "target 1 feature strategy=ort strategy-option=ignore-space-change conflict-style=zdiff3\n1 {} Commit 1\n",
+            commits[0].short
+        );
+        std::fs::write(&path, content).unwrap();
+
+        let plan = parse_routing_file(&path, &commits, &targets, true).unwrap();
+        let opts = &plan.target_options["feature"];
+        assert_eq!(opts.strategy.as_deref(), Some("ort"));
+        assert_eq!(opts.strategy_options, vec!["ignore-space-change"]);
+        assert_eq!(opts.conflict_style.as_deref(), Some("zdiff3"));
+
+        std::fs::write(&path, "target 1 feature conflict-style=fancy\n").unwrap();
+        let err = parse_routing_file(&path, &commits, &targets, true).unwrap_err();
+        assert!(err.to_string().contains("conflict-style"));
+
+        let content = format!(
+            "target 1 parser subdir=libs/parser/\n1 {} Commit 1\n",
+            commits[0].short
+        );
+        std::fs::write(&path, content).unwrap();
+        let plan = parse_routing_file(&path, &commits, &targets, true).unwrap();
+        assert_eq!(
+            plan.target_options["parser"].subdir.as_deref(),
+            Some("libs/parser")
+        );
+
+        std::fs::write(&path, "target 1 parser subdir=../elsewhere\n").unwrap();
+        let err = parse_routing_file(&path, &commits, &targets, true).unwrap_err();
+        assert!(err.to_string().contains("invalid subdir"));
+
+        let content = format!(
+            "target 1 login prefix=\"[AUTH-12] \" trailer=\"Issue: AUTH-12\" trailer=\"Note: say \\\"hi\\\"\"\n1 {} Commit 1\n",
+            commits[0].short
+        );
+        std::fs::write(&path, content).unwrap();
+        let plan = parse_routing_file(&path, &commits, &targets, true).unwrap();
+        let opts = &plan.target_options["login"];
+        assert_eq!(opts.prefix.as_deref(), Some("[AUTH-12] "));
+        assert_eq!(opts.trailers, vec!["Issue: AUTH-12", "Note: say \"hi\""]);
+
+        std::fs::write(&path, "target 1 login prefix=\"[AUTH-12] \n").unwrap();
+        let err = parse_routing_file(&path, &commits, &targets, true).unwrap_err();
+        assert!(err.to_string().contains("unterminated quote"));
+    }
+
+    #[test]
+    fn parse_new_dest_names_branches() {
+        let mut commits: Vec<CommitInfo> = (1..=4).map(mk_commit).collect();
+        commits[0].summary = "Add OAuth login (v2)".into();
+        commits[1].summary = "Add OAuth login: v2".into();
+        commits[2].summary = "!!!".into();
+        let mut targets = TargetDefs {
+            targets: vec![],
+            naming: BranchNaming {
+                prefix: Some("fw/".into()),
+                template: Some("{user}/{slug}".into()),
+                user: Some("jane-doe".into()),
+                taken: HashSet::from(["fw/jane-doe/add-oauth-login-v2".to_string()]),
+            },
+        };
+        let path = std::env::temp_dir().join("routing-test-new.txt");
+        let content = format!(
+            "target 1 docs\nnew {} a\nnew {} b\nnew {} c\n1 {} d\n",
+            commits[0].short, commits[1].short, commits[2].short, commits[3].short
+        );
+        std::fs::write(&path, content).unwrap();
+        let plan = parse_routing_file(&path, &commits, &targets, true).unwrap();
+        let names: Vec<_> = plan
+            .assignments
+            .iter()
+            .map(|a| match &a.dest {
+                Dest::Branch(b) => b.clone(),
+                _ => unreachable!(),
+            })
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                "fw/jane-doe/add-oauth-login-v2-2".to_string(),
+                "fw/jane-doe/add-oauth-login-v2-3".to_string(),
+                format!("fw/jane-doe/{}", commits[2].short),
+                "fw/docs".to_string(),
+            ]
+        );
+        assert!(plan.target_options.contains_key("fw/docs"));
+
+        targets.naming.template = Some("{who}/{slug}".into());
+        let err = parse_routing_file(&path, &commits, &targets, true).unwrap_err();
+        assert!(err.to_string().contains("unknown placeholder {who}"));
+    }
+
     #[test]
     fn parse_rejects_unknown_commit() {
         let commits = vec![mk_commit(1)];
-        let targets = TargetDefs { targets: vec![] };
+        let targets = TargetDefs::default();
         let path = std::env::temp_dir().join("routing-test-err.txt");
         std::fs::write(&path, "current deadbeef Missing\n").unwrap();
         let err = parse_routing_file(&path, &commits, &targets, true).unwrap_err();
         assert!(err.to_string().contains("unknown commit"));
     }
-}
-fn resolve_editor(args: &Args, repo: &Repository) -> String {
-    if let Some(e) = &args.editor {
-        return e.clone();
-    }
-    for var in ["GIT_SEQUENCE_EDITOR", "GIT_EDITOR"] {
-        if let Ok(v) = std::env::var(var)
-            && !v.trim().is_empty()
-        {
-            return v;
-        }
+
+    #[test]
+    fn parse_merge_picks() {
+        let mut merge = mk_commit(3);
+        merge.parents = 2;
+        merge.merged = vec![mk_commit(2).oid];
+        let commits = vec![mk_commit(1), merge];
+        let targets = TargetDefs::default();
+        let path = std::env::temp_dir().join("routing-test-merge.txt");
+
+        let content = format!(
+            "feature {} Commit 1\nfeature {} Merge\n",
+            commits[0].short, commits[1].short
+        );
+        std::fs::write(&path, content).unwrap();
+        let plan = parse_routing_file(&path, &commits, &targets, true).unwrap();
+        assert_eq!(plan.assignments[0].pick, Pick::Commit);
+        assert_eq!(
+            plan.assignments[1].pick,
+            Pick::Flatten(vec![mk_commit(2).oid])
+        );
+
+        let content = format!(
+            "drop {} Commit 1\nfeature^1 {} Merge\n",
+            commits[0].short, commits[1].short
+        );
+        std::fs::write(&path, content).unwrap();
+        let plan = parse_routing_file(&path, &commits, &targets, true).unwrap();
+        assert!(matches!(plan.assignments[0].dest, Dest::Drop));
+        assert_eq!(plan.assignments[1].pick, Pick::Mainline(1));
+
+        let content = format!(
+            "feature^1 {} Commit 1\nfeature^3 {} Merge\n",
+            commits[0].short, commits[1].short
+        );
+        std::fs::write(&path, content).unwrap();
+        let err = parse_routing_file(&path, &commits, &targets, true).unwrap_err();
+        assert!(err.to_string().contains("not a merge"));
     }
-    if let Ok(cfg) = repo.config()
-        && let Ok(v) = cfg.get_string("core.editor")
-        && !v.trim().is_empty()
-    {
-        return v;
+
+    #[test]
+    fn render_groups_commits_by_source() {
+        let mut other = mk_commit(2);
+        other.source = "other".into();
+        let commits = vec![mk_commit(1), other];
+        let path = render_routing_file(&TargetDefs::default(), &commits).unwrap();
+        let content = std::fs::read_to_string(&path).unwrap();
+        let from_work = content.find("# from work").unwrap();
+        let from_other = content.find("# from other").unwrap();
+        assert!(from_work < content.find(&commits[0].short).unwrap());
+        assert!(from_other > content.find(&commits[0].short).unwrap());
+        assert!(from_other < content.find(&commits[1].short).unwrap());
+
+        let path = render_routing_file(&TargetDefs::default(), &commits[..1]).unwrap();
+        assert!(!std::fs::read_to_string(&path).unwrap().contains("# from"));
     }
-    for var in ["VISUAL", "EDITOR"] {
-        if let Ok(v) = std::env::var(var)
-            && !v.trim().is_empty()
-        {
-            return v;
-        }
+
+    #[test]
+    fn autosquash_folds_follow_their_target() {
+        let mut commits: Vec<CommitInfo> = (1..=4).map(mk_commit).collect();
+        commits[2].summary = "fixup! Commit 1".into();
+        commits[3].summary = "squash! fixup! Commit 1".into();
+        let order: Vec<_> = autosquash_order(&commits)
+            .into_iter()
+            .map(|(c, fold)| (c.short.clone(), fold))
+            .collect();
+        assert_eq!(
+            order,
+            vec![
+                (commits[0].short.clone(), None),
+                (commits[2].short.clone(), Some(Fold::Fixup)),
+                (commits[3].short.clone(), Some(Fold::Squash)),
+                (commits[1].short.clone(), None),
+            ]
+        );
+
+        let path = std::env::temp_dir().join("routing-test-fold.txt");
+        let content = format!(
+            "target 1 feature\n1 {} Commit 1\nfixup {} fixup! Commit 1\nsquash {} squash!\ncurrent {} Commit 2\n",
+            commits[0].short, commits[2].short, commits[3].short, commits[1].short
+        );
+        std::fs::write(&path, content).unwrap();
+        let plan = parse_routing_file(&path, &commits, &TargetDefs::default(), true).unwrap();
+        assert!(matches!(&plan.assignments[1].dest, Dest::Branch(b) if b == "feature"));
+        assert_eq!(plan.assignments[1].pick, Pick::Fold(Fold::Fixup));
+        assert_eq!(plan.assignments[2].pick, Pick::Fold(Fold::Squash));
+
+        let content = format!("fixup {} fixup! Commit 1\n", commits[2].short);
+        std::fs::write(&path, content).unwrap();
+        let err = parse_routing_file(&path, &commits, &TargetDefs::default(), true).unwrap_err();
+        assert!(err.to_string().contains("no commit above it"));
     }
-    "vi".to_string()
 }
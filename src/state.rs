@@ -1,15 +1,105 @@
+use crate::routing::{Fold, TargetOptions};
+use crate::worktree_apply::{ApplyOptions, branch_tip, run_git, target_repo};
 use anyhow::{Context, Result, anyhow};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs;
+use std::io::Write;
 use std::path::{Path, PathBuf};
 use uuid::Uuid;
 
-const STATE_FILE: &str = "extract-state.json";
+/// Layout of the state file written by this build; older versions are migrated on load.
+pub const STATE_VERSION: u32 = 3;
+/// Single state file used before sessions were keyed by id.
+const LEGACY_STATE_FILE: &str = "extract-state.json";
+pub(crate) const LEGACY_WORKTREE: &str = "extract-wt";
+const STATE_PREFIX: &str = "extract-state-";
+const STATE_SUFFIX: &str = ".json";
+const LOCK_SUFFIX: &str = ".lock";
+/// Session worktrees are `.git/<prefix><session>` (parked branches add `-<n>`).
+pub(crate) const WORKTREE_PREFIX: &str = "extract-wt-";
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct BranchQueue {
+    /// Target as written in the routing file: `<branch>` or `<repo-path>:<branch>`.
     pub branch: String,
     pub commits: Vec<String>, // remaining commits (full shas), oldest -> newest
+    /// Merge commits picked against a parent (`<dest>^<n>`), keyed by sha.
+    #[serde(default)]
+    pub mainline: HashMap<String, u32>,
+    /// `fixup!`/`squash!` commits folded into the commit before them, keyed by sha.
+    #[serde(default)]
+    pub fold: HashMap<String, Fold>,
+    #[serde(default)]
+    pub applied: usize,
+    #[serde(default)]
+    pub created: bool,
+    #[serde(default)]
+    pub options: TargetOptions,
+    /// Stopped between picks by Ctrl-C; the first commit has not been applied yet.
+    #[serde(default)]
+    pub interrupted: bool,
+    /// Commits left out because the target already had them or they became empty.
+    #[serde(default)]
+    pub skipped: usize,
+    /// Signed source commits that were copied without a signature.
+    #[serde(default)]
+    pub unsigned: usize,
+    /// Branch tip as last seen or written by the session; `None` while the branch does not exist.
+    #[serde(default)]
+    pub tip: Option<String>,
+    /// Commit written for the previous queue entry in this session, `None` when that entry was
+    /// skipped or dropped; a `fixup`/`squash` pick is only folded into this commit.
+    #[serde(default)]
+    pub last_written: Option<String>,
+    /// Commit the branch's worktree was created at; `squash` collapses everything after it.
+    #[serde(default)]
+    pub worktree_start: Option<String>,
+    /// HEAD before the pick the session stopped at; if `--continue` finds HEAD still there,
+    /// the commit was skipped by hand.
+    #[serde(default)]
+    pub pick_base: Option<String>,
+    /// Another local repository (absolute path) the target branch lives in.
+    #[serde(default)]
+    pub repo: Option<String>,
+    /// Where new branches start in that repository, when it lacks the session base.
+    #[serde(default)]
+    pub start: Option<String>,
+}
+
+impl BranchQueue {
+    /// Branch name inside the repository the queue writes to.
+    pub fn branch_name(&self) -> &str {
+        match &self.repo {
+            Some(_) => self
+                .branch
+                .rsplit_once(':')
+                .map_or(&self.branch, |(_, b)| b),
+            None => &self.branch,
+        }
+    }
+}
+
+/// Where a source commit was routed, kept so conflicts can be explained against the plan.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Route {
+    pub sha: String,
+    pub dest: String,
+}
+
+/// A source the commits were collected from, and its tip when the session started.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RecordedSource {
+    /// Full ref name; `None` when the source was a plain commit.
+    pub refname: Option<String>,
+    pub oid: String,
+}
+
+/// A branch whose conflict was set aside by `--keep-going`, with its own worktree.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ParkedBranch {
+    pub branch_idx: usize,
+    pub worktree_path: String,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -22,61 +112,353 @@ pub struct SessionState {
     pub in_conflict: bool,
     pub base_oid: String,
     pub original_cwd: String,
+    #[serde(default)]
+    pub parked: Vec<ParkedBranch>,
+    #[serde(default)]
+    pub options: ApplyOptions,
+    #[serde(default)]
+    pub routes: Vec<Route>,
+    #[serde(default)]
+    pub sources: Vec<RecordedSource>,
+    #[serde(default)]
+    pub base_ref: Option<String>,
+    /// Version 2 recorded a single source; folded into `sources` on load.
+    #[serde(default, skip_serializing)]
+    source_ref: Option<String>,
+    #[serde(default, skip_serializing)]
+    source_oid: Option<String>,
 }
 
 impl SessionState {
     pub fn new(
-        worktree_path: &Path,
+        repo: &git2::Repository,
         branch_queues: Vec<BranchQueue>,
         base_oid: git2::Oid,
         original_cwd: &Path,
+        options: ApplyOptions,
     ) -> Self {
+        let session_id = Uuid::new_v4().to_string();
+        let worktree_path = session_worktree_path(repo, &session_id);
         Self {
-            version: 1,
-            session_id: Uuid::new_v4().to_string(),
+            version: STATE_VERSION,
+            session_id,
             worktree_path: worktree_path.to_string_lossy().into_owned(),
             current_branch_idx: 0,
             branch_queues,
             in_conflict: false,
             base_oid: base_oid.to_string(),
             original_cwd: original_cwd.to_string_lossy().into_owned(),
+            parked: Vec::new(),
+            options,
+            routes: Vec::new(),
+            sources: Vec::new(),
+            base_ref: None,
+            source_ref: None,
+            source_oid: None,
         }
     }
 }
 
-pub fn state_path(repo: &git2::Repository) -> PathBuf {
-    repo.path().join(STATE_FILE)
+/// Temp worktree used by a session; parked branches append `-<idx>`.
+pub fn session_worktree_path(repo: &git2::Repository, session_id: &str) -> PathBuf {
+    repo.path().join(format!("{WORKTREE_PREFIX}{session_id}"))
 }
 
-pub fn load_state(repo: &git2::Repository) -> Result<SessionState> {
-    let path = state_path(repo);
+pub fn state_path(repo: &git2::Repository, session_id: &str) -> PathBuf {
+    repo.path()
+        .join(format!("{STATE_PREFIX}{session_id}{STATE_SUFFIX}"))
+}
+
+fn lock_path(repo: &git2::Repository, session_id: &str) -> PathBuf {
+    repo.path()
+        .join(format!("{STATE_PREFIX}{session_id}{LOCK_SUFFIX}"))
+}
+
+/// Ids of all sessions that have a state file, sorted for stable output.
+pub fn list_sessions(repo: &git2::Repository) -> Result<Vec<String>> {
+    let mut ids = Vec::new();
+    for entry in fs::read_dir(repo.path()).context("listing git dir")? {
+        let name = entry?.file_name().to_string_lossy().into_owned();
+        if let Some(id) = name
+            .strip_prefix(STATE_PREFIX)
+            .and_then(|rest| rest.strip_suffix(STATE_SUFFIX))
+        {
+            ids.push(id.to_string());
+        }
+    }
+    ids.sort();
+    Ok(ids)
+}
+
+/// The given session id, or the only existing session's when none is given (a legacy state
+/// file counts as a session). Nothing is written, so this is safe before `lock_session`.
+pub fn resolve_session(repo: &git2::Repository, session_id: Option<&str>) -> Result<String> {
+    if let Some(id) = session_id {
+        return Ok(id.to_string());
+    }
+    let mut ids = list_sessions(repo)?;
+    if let Some(id) = legacy_session_id(repo)
+        && !ids.contains(&id)
+    {
+        ids.push(id);
+    }
+    match ids.len() {
+        0 => Err(anyhow!("no extract session found")),
+        1 => Ok(ids.remove(0)),
+        _ => Err(anyhow!(
+            "several extract sessions exist; pick one with --session <id>: {}",
+            ids.join(", ")
+        )),
+    }
+}
+
+/// Load a session, moving a legacy state file and an older state version to the current
+/// layout on disk. Both write the state file, so the caller must hold the session's lock.
+pub fn load_state(repo: &git2::Repository, session_id: &str) -> Result<SessionState> {
+    if legacy_session_id(repo).as_deref() == Some(session_id) {
+        migrate_legacy_state(repo)?;
+        eprintln!("note: migrated legacy extract state to session {session_id}");
+    }
+    let (state, upgraded) = read_state(repo, session_id)?;
+    if upgraded {
+        save_state(repo, &state)?;
+    }
+    Ok(state)
+}
+
+/// Parse a session's state file, upgrading an older version in memory only; returns whether
+/// it was upgraded. For looking at sessions another process may hold.
+pub(crate) fn read_state(
+    repo: &git2::Repository,
+    session_id: &str,
+) -> Result<(SessionState, bool)> {
+    let path = state_path(repo, session_id);
     let content =
         fs::read_to_string(&path).with_context(|| format!("reading state file {path:?}"))?;
-    let state: SessionState = serde_json::from_str(&content).context("parsing state file")?;
-    Ok(state)
+    let mut state: SessionState = serde_json::from_str(&content).context("parsing state file")?;
+    if state.version > STATE_VERSION {
+        return Err(anyhow!(
+            "state file {path:?} has version {} but this git-extract understands up to {STATE_VERSION}; finish the session with the newer git-extract",
+            state.version
+        ));
+    }
+    if state.version == STATE_VERSION {
+        return Ok((state, false));
+    }
+    // version 1 lacks the recorded refs (serde defaults fill them); version 2 had one source
+    if let Some(oid) = state.source_oid.take() {
+        state.sources.push(RecordedSource {
+            refname: state.source_ref.take(),
+            oid,
+        });
+    }
+    state.version = STATE_VERSION;
+    Ok((state, true))
+}
+
+/// Session id recorded in a pre-session-id `.git/extract-state.json`, if there is one.
+pub(crate) fn legacy_session_id(repo: &git2::Repository) -> Option<String> {
+    let content = fs::read_to_string(repo.path().join(LEGACY_STATE_FILE)).ok()?;
+    serde_json::from_str::<SessionState>(&content)
+        .ok()
+        .map(|state| state.session_id)
+}
+
+/// Move a pre-session-id `.git/extract-state.json` (and its `.git/extract-wt` worktree) to the
+/// per-session layout. Returns the migrated session id.
+pub fn migrate_legacy_state(repo: &git2::Repository) -> Result<Option<String>> {
+    let legacy = repo.path().join(LEGACY_STATE_FILE);
+    let Ok(content) = fs::read_to_string(&legacy) else {
+        return Ok(None);
+    };
+    let mut state: SessionState =
+        serde_json::from_str(&content).context("parsing legacy state file")?;
+    let old_wt = PathBuf::from(&state.worktree_path);
+    let new_wt = session_worktree_path(repo, &state.session_id);
+    if old_wt.exists() && old_wt.ends_with(LEGACY_WORKTREE) {
+        run_git(
+            repo.path(),
+            [
+                "worktree",
+                "move",
+                old_wt.to_str().unwrap(),
+                new_wt.to_str().unwrap(),
+            ],
+        )
+        .context("moving legacy worktree")?;
+    }
+    state.worktree_path = new_wt.to_string_lossy().into_owned();
+    save_state(repo, &state)?;
+    fs::remove_file(&legacy).with_context(|| format!("removing {legacy:?}"))?;
+    Ok(Some(state.session_id))
+}
+
+/// Compare the refs recorded when the session started with the repository as it is now.
+///
+/// Moved targets are refused because finishing the session would overwrite someone else's
+/// commits; a moved source or base only changes what a fresh run would see, so it is a warning.
+pub fn verify_refs(repo: &git2::Repository, state: &SessionState) -> Result<Vec<String>> {
+    let mut warnings = Vec::new();
+    if state.sources.is_empty() {
+        warnings.push("session predates ref tracking; not checking for moved branches".into());
+        return Ok(warnings);
+    }
+    let resolve = |name: &str| {
+        repo.revparse_single(name)
+            .ok()
+            .map(|obj| obj.id().to_string())
+    };
+
+    for recorded in &state.sources {
+        let Some(source) = &recorded.refname else {
+            continue;
+        };
+        let source_oid = &recorded.oid;
+        match resolve(source) {
+            Some(now) if &now == source_oid => {}
+            Some(now) => {
+                let advanced = match (git2::Oid::from_str(source_oid), git2::Oid::from_str(&now)) {
+                    (Ok(old), Ok(new)) => repo.graph_descendant_of(new, old).unwrap_or(false),
+                    _ => false,
+                };
+                warnings.push(if advanced {
+                    format!(
+                        "source {source} gained commits since the session started; they are not part of this session"
+                    )
+                } else {
+                    format!(
+                        "source {source} was rewritten since the session started ({} -> {}); the queued commits are applied as originally planned",
+                        &source_oid[..7],
+                        &now[..7]
+                    )
+                });
+            }
+            None => warnings.push(format!(
+                "source {source} no longer exists; the queued commits are applied as originally planned"
+            )),
+        }
+    }
+    if let Some(base) = &state.base_ref
+        && let Some(now) = resolve(base)
+        && now != state.base_oid
+    {
+        warnings.push(format!(
+            "base {base} moved since the session started; new branches still start from {}",
+            &state.base_oid[..7]
+        ));
+    }
+
+    let mut problems = Vec::new();
+    for queue in &state.branch_queues {
+        for sha in &queue.commits {
+            let missing = git2::Oid::from_str(sha)
+                .ok()
+                .and_then(|oid| repo.find_commit(oid).ok())
+                .is_none();
+            if missing {
+                problems.push(format!(
+                    "queued commit {sha} for {} no longer exists",
+                    queue.branch
+                ));
+            }
+        }
+        let now = target_repo(repo, queue)
+            .ok()
+            .and_then(|target| branch_tip(&target, queue.branch_name()));
+        if now == queue.tip {
+            continue;
+        }
+        let short = |oid: &Option<String>| match oid {
+            Some(oid) => oid[..7].to_string(),
+            None => "(none)".to_string(),
+        };
+        problems.push(format!(
+            "target {} moved since the session paused (expected {}, found {})",
+            queue.branch,
+            short(&queue.tip),
+            short(&now)
+        ));
+    }
+    if !problems.is_empty() {
+        return Err(anyhow!(
+            "refusing to continue session {}:\n  {}\nrestore the branches or run git extract --abort",
+            state.session_id,
+            problems.join("\n  ")
+        ));
+    }
+    Ok(warnings)
 }
 
 pub fn save_state(repo: &git2::Repository, state: &SessionState) -> Result<()> {
-    let path = state_path(repo);
+    let path = state_path(repo, &state.session_id);
     let data = serde_json::to_string_pretty(state)?;
     fs::write(&path, data).with_context(|| format!("writing state file {path:?}"))?;
     Ok(())
 }
 
-pub fn remove_state(repo: &git2::Repository) -> Result<()> {
-    let path = state_path(repo);
+pub fn remove_state(repo: &git2::Repository, session_id: &str) -> Result<()> {
+    let path = state_path(repo, session_id);
     if path.exists() {
         fs::remove_file(&path).with_context(|| format!("removing state file {path:?}"))?;
     }
     Ok(())
 }
 
-pub fn ensure_no_state(repo: &git2::Repository) -> Result<()> {
-    let path = state_path(repo);
-    if path.exists() {
-        return Err(anyhow!(
-            "an extract session is already in progress; run --continue or --abort"
-        ));
+/// Exclusive claim on a session, released when dropped.
+#[derive(Debug)]
+pub struct SessionLock {
+    path: PathBuf,
+}
+
+impl Drop for SessionLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
     }
-    Ok(())
+}
+
+pub fn lock_session(repo: &git2::Repository, session_id: &str) -> Result<SessionLock> {
+    let path = lock_path(repo, session_id);
+    let mut file = match fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&path)
+    {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+            let holder = fs::read_to_string(&path).unwrap_or_default();
+            return Err(anyhow!(
+                "session {session_id} is in use by another git-extract process (pid {}); run git extract --repair if it is gone",
+                holder.trim()
+            ));
+        }
+        Err(e) => return Err(e).with_context(|| format!("creating lock file {path:?}")),
+    };
+    writeln!(file, "{}", std::process::id())?;
+    Ok(SessionLock { path })
+}
+
+/// Remove the session's lock when the process that took it no longer exists.
+///
+/// Returns `true` if a stale lock was removed.
+pub fn clear_stale_lock(repo: &git2::Repository, session_id: &str) -> Result<bool> {
+    let path = lock_path(repo, session_id);
+    let Ok(content) = fs::read_to_string(&path) else {
+        return Ok(false);
+    };
+    let alive = content
+        .trim()
+        .parse::<i32>()
+        .map(process_alive)
+        .unwrap_or(false);
+    if alive {
+        return Ok(false);
+    }
+    fs::remove_file(&path).with_context(|| format!("removing lock file {path:?}"))?;
+    Ok(true)
+}
+
+pub(crate) fn process_alive(pid: i32) -> bool {
+    // SAFETY: signal 0 performs only the existence/permission check.
+    let rc = unsafe { libc::kill(pid, 0) };
+    rc == 0 || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
 }
@@ -0,0 +1,111 @@
+use crate::git_ops::subtree;
+use crate::worktree_apply::{run_git, run_git_status, sign_config};
+use anyhow::{Context, Result, anyhow};
+use git2::{Oid, Repository};
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// Result of replaying one commit into a `subdir=` target.
+pub(crate) enum SubdirPick {
+    Applied,
+    /// The commit changes nothing below the subdirectory, or nothing the target lacks.
+    Empty,
+    Conflict(String),
+}
+
+/// Root commit a new `subdir=` branch starts from: the subdirectory as it is in `base`.
+pub(crate) fn root_commit(repo: &Repository, base: Oid, subdir: &str, sign: bool) -> Result<Oid> {
+    let tree = match subtree(repo, repo.find_commit(base)?.tree()?, Some(subdir))? {
+        Some(tree) => tree.id(),
+        None => repo.treebuilder(None)?.write()?,
+    };
+    let message = format!("Extract {subdir} at {}", &base.to_string()[..7]);
+    let sign_flag = if sign { "--gpg-sign" } else { "--no-gpg-sign" };
+    let dir = repo.workdir().unwrap_or(repo.path());
+    let tree = tree.to_string();
+    let oid = run_git(dir, ["commit-tree", sign_flag, "-m", &message, &tree])?;
+    Oid::from_str(oid.trim()).context("parse root commit")
+}
+
+/// Replay `sha` in `wt_path` with its paths made relative to `subdir`, dropping changes
+/// outside of it, and commit it with the original author and message.
+pub(crate) fn pick_into_subdir(
+    wt_path: &Path,
+    sha: &str,
+    mainline: Option<u32>,
+    subdir: &str,
+    keep_empty: bool,
+    sign: bool,
+) -> Result<SubdirPick> {
+    let parent = format!("{sha}^{}", mainline.unwrap_or(1));
+    let mut diff = Command::new("git");
+    diff.arg("-C")
+        .arg(wt_path)
+        .args(["diff-tree", "-p", "--binary", "--full-index"])
+        .arg(format!("--relative={subdir}/"));
+    if run_git_status(wt_path, ["rev-parse", "--verify", "--quiet", &parent]).is_ok() {
+        diff.arg(&parent);
+    } else {
+        diff.arg("--root");
+    }
+    let output = diff.arg(sha).output().context("running git diff-tree")?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow!("git diff-tree failed: {stderr}"));
+    }
+
+    if !output.stdout.is_empty() {
+        let mut apply = Command::new("git")
+            .arg("-C")
+            .arg(wt_path)
+            .args(["apply", "-3", "--index"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .context("running git apply")?;
+        if let Some(mut stdin) = apply.stdin.take() {
+            stdin.write_all(&output.stdout)?;
+        }
+        let applied = apply.wait_with_output()?;
+        if !applied.status.success() {
+            return Ok(SubdirPick::Conflict(
+                String::from_utf8_lossy(&applied.stderr).into_owned(),
+            ));
+        }
+    }
+    if !has_staged_changes(wt_path) {
+        if !keep_empty {
+            return Ok(SubdirPick::Empty);
+        }
+        commit(wt_path, sha, sign, true)?;
+        return Ok(SubdirPick::Applied);
+    }
+    commit(wt_path, sha, sign, false)?;
+    Ok(SubdirPick::Applied)
+}
+
+/// Commit the resolution of a stopped subdir pick; returns false when nothing was staged.
+pub(crate) fn finish_pick(wt_path: &Path, sha: &str, sign: bool) -> Result<bool> {
+    if !has_staged_changes(wt_path) {
+        return Ok(false);
+    }
+    commit(wt_path, sha, sign, false)?;
+    Ok(true)
+}
+
+/// Commit the index reusing the author and message of `sha`.
+fn commit(wt_path: &Path, sha: &str, sign: bool, allow_empty: bool) -> Result<()> {
+    let mut args: Vec<&str> = sign_config(sign).to_vec();
+    args.extend(["commit", "--quiet", "--no-verify", "-C", sha]);
+    if allow_empty {
+        args.push("--allow-empty");
+    }
+    run_git(wt_path, args)?;
+    Ok(())
+}
+
+fn has_staged_changes(wt_path: &Path) -> bool {
+    run_git_status(wt_path, ["diff", "--cached", "--quiet"]).is_err()
+}
@@ -1,28 +1,244 @@
-use crate::git_ops::BaseInfo;
-use crate::routing::{ApplySummary, Dest, RoutingPlan};
-use crate::state::{BranchQueue, SessionState};
+use crate::conflict::unmerged_paths;
+use crate::git_ops::{BaseInfo, SourceInfo, is_signed, patch_id, patch_ids_since};
+use crate::import::remove_imports;
+use crate::routing::{ApplySummary, Dest, Fold, Pick, RoutingPlan, TargetOptions};
+use crate::state::{
+    BranchQueue, ParkedBranch, RecordedSource, Route, SessionState, save_state,
+    session_worktree_path,
+};
+use crate::subdir::{self, SubdirPick};
 use anyhow::{Context, Result, anyhow};
+use clap::ValueEnum;
 use git2::{BranchType, Oid, Repository};
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::io::Write;
 use std::path::{Path, PathBuf};
-use std::process::Command;
-use uuid::Uuid;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
 
 #[derive(Debug)]
 pub enum ApplyOutcome {
     Complete(ApplySummary),
-    Conflict(SessionState, String),
+    Conflict(Box<SessionState>, String),
+    /// Ctrl-C during apply; the state is consistent and can be resumed with `--continue`.
+    Interrupted(Box<SessionState>),
 }
 
-pub fn apply_plan(repo: &Repository, plan: &RoutingPlan, base: &BaseInfo) -> Result<ApplyOutcome> {
+/// Session-wide apply settings; persisted in the state file so `--continue` behaves the same.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ApplyOptions {
+    pub keep_going: bool,
+    #[serde(default)]
+    pub strategy: Option<String>,
+    #[serde(default)]
+    pub strategy_options: Vec<String>,
+    #[serde(default)]
+    pub conflict_style: Option<String>,
+    #[serde(default)]
+    pub empty: EmptyMode,
+    /// Sign written commits; resolved from `--sign`/`--no-sign` or `commit.gpgSign`.
+    #[serde(default)]
+    pub sign: bool,
+    #[serde(default)]
+    pub committer_date_is_author_date: bool,
+    #[serde(default)]
+    pub reset_author: bool,
+    #[serde(default)]
+    pub signoff: bool,
+    #[serde(default)]
+    pub record_origin: RecordOrigin,
+    /// Squash every target (`--squash-targets`); a target's own `squash` token wins.
+    #[serde(default)]
+    pub squash: Option<SquashMode>,
+    /// Resolved editor for `squash=edit` messages.
+    #[serde(default)]
+    pub editor: Option<String>,
+    /// Move targets checked out in a worktree with local changes (`--force`).
+    #[serde(default)]
+    pub force: bool,
+}
+
+/// What to do with a commit whose change is already on the target (`--empty`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EmptyMode {
+    /// Skip it with a note
+    #[default]
+    Drop,
+    /// Record it as an empty commit
+    Keep,
+    /// Stop like a conflict so it can be decided by hand
+    Stop,
+}
+
+/// How an extracted commit points back at its source (`--record-origin`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RecordOrigin {
+    /// Leave no trace
+    #[default]
+    None,
+    /// Add an Extracted-From trailer to the message
+    Trailer,
+    /// Add a note under refs/notes/extract with the source sha and session id
+    Notes,
+}
+
+/// How the commits of a `squash` target are collapsed (`squash`/`squash=edit`, `--squash-targets`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SquashMode {
+    /// Use the generated message
+    Plain,
+    /// Open the generated message in the editor
+    Edit,
+}
+
+/// Our amends copy notes themselves (`copy_notes`), including the `--record-origin` ref.
+const NO_AMEND_NOTES: [&str; 2] = ["-c", "notes.rewrite.amend=false"];
+
+const ORIGIN_TRAILER: &str = "Extracted-From";
+const ORIGIN_NOTES_REF: &str = "refs/notes/extract";
+
+/// Replay and record conflict resolutions; rr-cache lives in the common dir, shared with the main repo.
+const RERERE_CONFIG: [&str; 4] = ["-c", "rerere.enabled=true", "-c", "rerere.autoUpdate=true"];
+
+/// Pin `commit.gpgSign` for every commit git writes for us, including `--continue` of a
+/// single-commit cherry-pick, which does not remember `--gpg-sign`.
+pub(crate) fn sign_config(sign: bool) -> [&'static str; 2] {
+    if sign {
+        ["-c", "commit.gpgSign=true"]
+    } else {
+        ["-c", "commit.gpgSign=false"]
+    }
+}
+
+pub(crate) const BACKUP_REF_PREFIX: &str = "refs/extract/backup/";
+/// Commits copied into a fork for picking, kept reachable until the session ends.
+const INCOMING_REF_PREFIX: &str = "refs/extract/incoming/";
+
+enum BranchStep {
+    Done,
+    Conflict(String),
+    Interrupted,
+}
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_sigint(_: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// Turns Ctrl-C into a flag checked between picks while applying; restores the old handler on drop.
+struct InterruptGuard {
+    previous: libc::sighandler_t,
+}
+
+impl InterruptGuard {
+    fn install() -> Self {
+        INTERRUPTED.store(false, Ordering::SeqCst);
+        let handler = on_sigint as extern "C" fn(libc::c_int);
+        // SAFETY: the handler only stores to an atomic, which is async-signal-safe.
+        let previous = unsafe { libc::signal(libc::SIGINT, handler as libc::sighandler_t) };
+        InterruptGuard { previous }
+    }
+}
+
+impl Drop for InterruptGuard {
+    fn drop(&mut self) {
+        // SAFETY: restores the disposition that was active before `install`.
+        unsafe {
+            libc::signal(libc::SIGINT, self.previous);
+        }
+    }
+}
+
+/// Build the state for a fresh run; the caller locks the session before `apply_plan`.
+pub fn new_session(
+    repo: &Repository,
+    plan: &RoutingPlan,
+    base: &BaseInfo,
+    sources: &[SourceInfo],
+    options: &ApplyOptions,
+) -> Result<SessionState> {
     let repo_path = repo
         .workdir()
         .ok_or_else(|| anyhow!("repository has no working directory"))?;
 
-    let queues = build_branch_queues(plan);
-    let wt_path = repo.path().join("extract-wt");
-    cleanup_worktree(repo_path, &wt_path)?;
-    apply_with_queues(repo, repo_path, base, queues, wt_path, None)
+    let mut queues = build_branch_queues(plan);
+    for queue in &mut queues {
+        // `<path>:<branch>` targets a branch in another local repository
+        if let Some((path, _)) = queue.branch.rsplit_once(':') {
+            let fork_path = repo_path.join(path);
+            let fork = Repository::open(&fork_path).with_context(|| {
+                format!(
+                    "target {}: {} is not a git repository",
+                    queue.branch,
+                    fork_path.display()
+                )
+            })?;
+            let dir = command_dir(&fork).canonicalize()?;
+            queue.repo = Some(dir.to_string_lossy().into_owned());
+        }
+        queue.tip = branch_tip(&target_repo(repo, queue)?, queue.branch_name());
+    }
+    if !options.force {
+        let mut busy = Vec::new();
+        for queue in &queues {
+            if let Some(wt) = checked_out_in(&target_repo(repo, queue)?, queue.branch_name())?
+                && has_local_changes(&wt)?
+            {
+                busy.push(format!("{} in {}", queue.branch, wt.display()));
+            }
+        }
+        if !busy.is_empty() {
+            return Err(anyhow!(
+                "target branch(es) checked out in a worktree with local changes:\n  {}\ncommit or stash them, or pass --force to move the branch without updating that worktree",
+                busy.join("\n  ")
+            ));
+        }
+    }
+    let mut state = SessionState::new(repo, queues, base.base_oid, repo_path, options.clone());
+    for a in &plan.assignments {
+        let dest = match &a.dest {
+            Dest::Branch(b) => b.clone(),
+            Dest::Current => "current".to_string(),
+            Dest::Drop => "drop".to_string(),
+        };
+        // a flattened merge is represented by the commits it brought in
+        let shas = match &a.pick {
+            Pick::Flatten(merged) => merged.clone(),
+            _ => vec![a.oid],
+        };
+        for sha in shas {
+            state.routes.push(Route {
+                sha: sha.to_string(),
+                dest: dest.clone(),
+            });
+        }
+    }
+    state.sources = sources
+        .iter()
+        .map(|s| RecordedSource {
+            refname: s.refname.clone(),
+            oid: s.tip.to_string(),
+        })
+        .collect();
+    state.base_ref = base.base_ref.clone();
+    Ok(state)
+}
+
+pub fn apply_plan(repo: &Repository, state: SessionState) -> Result<ApplyOutcome> {
+    let repo_path = repo
+        .workdir()
+        .ok_or_else(|| anyhow!("repository has no working directory"))?;
+    cleanup_worktree(repo_path, Path::new(&state.worktree_path))?;
+    apply_with_queues(repo, state)
 }
 
 pub fn resume_session(
@@ -35,24 +251,24 @@ pub fn resume_session(
         .workdir()
         .ok_or_else(|| anyhow!("repository has no working directory"))?;
     let wt_path = PathBuf::from(&state.worktree_path);
-    if !wt_path.exists() {
-        return Err(anyhow!("saved worktree path missing; cannot continue"));
+    if state.in_conflict {
+        if !wt_path.exists() {
+            return Err(anyhow!(
+                "saved worktree path missing; run git extract --repair"
+            ));
+        }
+        // a stopped subdir= pick commits whatever the user staged, like a pending cherry-pick
+        let subdir_pick = state
+            .branch_queues
+            .get(state.current_branch_idx)
+            .is_some_and(|q| q.options.subdir.is_some());
+        ensure_worktree_clean(&wt_path, allow_dirty, subdir_pick)?;
     }
-    ensure_worktree_clean(&wt_path, allow_dirty)?;
     let orig_cwd = std::env::current_dir().unwrap_or(repo_path.to_path_buf());
     if !no_chdir {
         let _ = std::env::set_current_dir(&wt_path);
     }
-    let res = apply_with_queues(
-        repo,
-        repo_path,
-        &BaseInfo {
-            base_oid: Oid::from_str(&state.base_oid)?,
-        },
-        state.branch_queues.clone(),
-        wt_path.clone(),
-        Some(state),
-    );
+    let res = apply_with_queues(repo, state);
     if !no_chdir {
         let _ = std::env::set_current_dir(&orig_cwd);
     }
@@ -60,8 +276,23 @@ pub fn resume_session(
 }
 
 pub fn abort_session(repo: &Repository, state: &SessionState, no_chdir: bool) -> Result<()> {
-    let wt_path = PathBuf::from(&state.worktree_path);
-    if wt_path.exists() {
+    let worktrees = std::iter::once((state.current_branch_idx, &state.worktree_path))
+        .chain(
+            state
+                .parked
+                .iter()
+                .map(|p| (p.branch_idx, &p.worktree_path)),
+        )
+        .map(|(idx, wt)| (idx, PathBuf::from(wt)));
+    for (idx, wt_path) in worktrees {
+        if !wt_path.exists() {
+            continue;
+        }
+        // the worktree is registered in the repository its branch lives in
+        let owner = match state.branch_queues.get(idx).and_then(|q| q.repo.as_ref()) {
+            Some(fork) => PathBuf::from(fork),
+            None => repo.path().to_path_buf(),
+        };
         if !no_chdir {
             let _ = std::env::set_current_dir(&wt_path);
         }
@@ -70,27 +301,50 @@ pub fn abort_session(repo: &Repository, state: &SessionState, no_chdir: bool) ->
             ["-C", wt_path.to_str().unwrap(), "cherry-pick", "--abort"],
         );
         let _ = run_git(
-            repo.path(),
+            &owner,
             ["worktree", "remove", "--force", wt_path.to_str().unwrap()],
         );
         if !no_chdir {
             let _ = std::env::set_current_dir(repo.path().parent().unwrap_or(Path::new(".")));
         }
     }
+    remove_session_refs(repo, state)?;
     Ok(())
 }
 
+fn parked_worktree_path(wt_path: &Path, branch_idx: usize) -> PathBuf {
+    PathBuf::from(format!("{}-{branch_idx}", wt_path.display()))
+}
+
 fn build_branch_queues(plan: &RoutingPlan) -> Vec<BranchQueue> {
     let mut order: Vec<String> = Vec::new();
     let mut map: HashMap<String, Vec<String>> = HashMap::new();
+    let mut mainlines: HashMap<String, HashMap<String, u32>> = HashMap::new();
+    let mut folds: HashMap<String, HashMap<String, Fold>> = HashMap::new();
     for assign in &plan.assignments {
         if let Dest::Branch(b) = &assign.dest {
             if !map.contains_key(b) {
                 order.push(b.clone());
             }
-            map.entry(b.clone())
-                .or_default()
-                .push(assign.oid.to_string());
+            let commits = map.entry(b.clone()).or_default();
+            match &assign.pick {
+                Pick::Commit => commits.push(assign.oid.to_string()),
+                Pick::Mainline(n) => {
+                    commits.push(assign.oid.to_string());
+                    mainlines
+                        .entry(b.clone())
+                        .or_default()
+                        .insert(assign.oid.to_string(), *n);
+                }
+                Pick::Flatten(merged) => commits.extend(merged.iter().map(Oid::to_string)),
+                Pick::Fold(fold) => {
+                    commits.push(assign.oid.to_string());
+                    folds
+                        .entry(b.clone())
+                        .or_default()
+                        .insert(assign.oid.to_string(), *fold);
+                }
+            }
         }
     }
     order
@@ -98,116 +352,888 @@ fn build_branch_queues(plan: &RoutingPlan) -> Vec<BranchQueue> {
         .map(|b| BranchQueue {
             branch: b.clone(),
             commits: map.remove(&b).unwrap_or_default(),
+            mainline: mainlines.remove(&b).unwrap_or_default(),
+            fold: folds.remove(&b).unwrap_or_default(),
+            applied: 0,
+            created: false,
+            interrupted: false,
+            skipped: 0,
+            unsigned: 0,
+            last_written: None,
+            worktree_start: None,
+            pick_base: None,
+            tip: None,
+            repo: None,
+            start: None,
+            options: plan.target_options.get(&b).cloned().unwrap_or_default(),
         })
         .collect()
 }
 
-fn apply_with_queues(
-    repo: &Repository,
-    repo_path: &Path,
-    base: &BaseInfo,
-    mut queues: Vec<BranchQueue>,
-    wt_path: PathBuf,
-    mut state_opt: Option<SessionState>,
-) -> Result<ApplyOutcome> {
-    let mut created = Vec::new();
-    let mut commits_per_branch: HashMap<String, usize> = HashMap::new();
-    let mut current_idx = state_opt
-        .as_ref()
-        .map(|s| s.current_branch_idx)
-        .unwrap_or(0);
-    let mut in_conflict = state_opt.as_ref().map(|s| s.in_conflict).unwrap_or(false);
+fn apply_with_queues(repo: &Repository, mut state: SessionState) -> Result<ApplyOutcome> {
+    let _interrupt = InterruptGuard::install();
+    let base_oid = Oid::from_str(&state.base_oid)?;
+    let wt_path = session_worktree_path(repo, &state.session_id);
+    let notes_refs = notes_rewrite_refs(repo);
 
-    while current_idx < queues.len() {
-        let branch = queues[current_idx].branch.clone();
-        // setup worktree for this branch if starting fresh
-        if state_opt.is_none() || !in_conflict {
-            cleanup_worktree(repo_path, &wt_path)?;
-            let (start_spec, branch_existed) = branch_start_spec(repo, &branch, base.base_oid)?;
-            if !branch_existed {
-                created.push(branch.clone());
+    // branches parked by --keep-going get another attempt first
+    let mut pending = std::mem::take(&mut state.parked).into_iter();
+    while let Some(parked) = pending.next() {
+        let parked_wt = PathBuf::from(&parked.worktree_path);
+        let queue = &mut state.branch_queues[parked.branch_idx];
+        if !parked_wt.exists() {
+            return Err(anyhow!(
+                "parked worktree for {} is missing; run git extract --repair",
+                queue.branch
+            ));
+        }
+        if !unmerged_paths(&parked_wt)?.is_empty() {
+            eprintln!(
+                "note: {} still has unresolved conflicts; leaving it parked",
+                queue.branch
+            );
+            state.parked.push(parked);
+            continue;
+        }
+        let target = target_repo(repo, queue)?;
+        let ctx = BranchCtx {
+            repo: &target,
+            source: repo,
+            notes_refs: &notes_refs,
+            options: &state.options,
+            session_id: &state.session_id,
+            base_oid: queue_base(queue, base_oid)?,
+        };
+        let step = apply_branch(&ctx, queue, &parked_wt, true)?;
+        match step {
+            BranchStep::Done => cleanup_worktree(command_dir(&target), &parked_wt)?,
+            BranchStep::Conflict(_) => state.parked.push(parked),
+            BranchStep::Interrupted => {
+                state.parked.push(parked);
+                state.parked.extend(pending);
+                return Ok(ApplyOutcome::Interrupted(Box::new(state)));
             }
-            run_git(
-                repo_path,
-                [
-                    "worktree",
-                    "add",
-                    "--detach",
-                    wt_path.to_str().unwrap(),
-                    &start_spec,
-                ],
+        }
+        save_state(repo, &state)?;
+    }
+
+    while state.current_branch_idx < state.branch_queues.len() {
+        let idx = state.current_branch_idx;
+        let resuming = state.in_conflict;
+        // setup worktree for this branch if starting fresh
+        if !resuming {
+            // checkpoint first so a crash after `worktree add` still leaves a resumable session
+            save_state(repo, &state)?;
+            start_worktree(
+                repo,
+                &state.session_id,
+                &mut state.branch_queues[idx],
+                base_oid,
+                &wt_path,
+                state.options.sign,
             )?;
         }
+        state.in_conflict = false;
 
-        // if resuming from conflict, finish current cherry-pick first
-        if in_conflict {
-            if cherry_pick_in_progress(&wt_path)? {
+        let queue = &mut state.branch_queues[idx];
+        let target = target_repo(repo, queue)?;
+        let target_path = command_dir(&target);
+        let ctx = BranchCtx {
+            repo: &target,
+            source: repo,
+            notes_refs: &notes_refs,
+            options: &state.options,
+            session_id: &state.session_id,
+            base_oid: queue_base(queue, base_oid)?,
+        };
+        let step = apply_branch(&ctx, queue, &wt_path, resuming)?;
+        match step {
+            BranchStep::Done => cleanup_worktree(target_path, &wt_path)?,
+            BranchStep::Conflict(msg) if state.options.keep_going => {
+                let parked_wt = parked_worktree_path(&wt_path, idx);
+                cleanup_worktree(target_path, &parked_wt)?;
                 run_git(
-                    repo_path,
-                    ["-C", wt_path.to_str().unwrap(), "cherry-pick", "--continue"],
+                    target_path,
+                    [
+                        "worktree",
+                        "move",
+                        wt_path.to_str().unwrap(),
+                        parked_wt.to_str().unwrap(),
+                    ],
                 )
-                .context("continuing cherry-pick")?;
+                .context("parking conflicted worktree")?;
+                eprintln!(
+                    "note: parked {} after conflict: {}",
+                    queue.branch,
+                    msg.trim()
+                );
+                state.parked.push(ParkedBranch {
+                    branch_idx: idx,
+                    worktree_path: parked_wt.to_string_lossy().into_owned(),
+                });
+            }
+            BranchStep::Conflict(msg) => {
+                state.in_conflict = true;
+                state.worktree_path = wt_path.to_string_lossy().into_owned();
+                return Ok(ApplyOutcome::Conflict(Box::new(state), msg));
+            }
+            BranchStep::Interrupted => {
+                state.in_conflict = true;
+                state.worktree_path = wt_path.to_string_lossy().into_owned();
+                return Ok(ApplyOutcome::Interrupted(Box::new(state)));
+            }
+        }
+        state.current_branch_idx += 1;
+        save_state(repo, &state)?;
+    }
+
+    if let Some(first) = state.parked.first() {
+        state.worktree_path = first.worktree_path.clone();
+        let msg = format!("{} branch(es) need resolution", state.parked.len());
+        return Ok(ApplyOutcome::Conflict(Box::new(state), msg));
+    }
+
+    remove_session_refs(repo, &state)?;
+    let mut created = Vec::new();
+    let mut commits_per_branch: HashMap<String, usize> = HashMap::new();
+    let mut skipped_per_branch: HashMap<String, usize> = HashMap::new();
+    let mut unsigned_per_branch: HashMap<String, usize> = HashMap::new();
+    for queue in &state.branch_queues {
+        if queue.created {
+            created.push(queue.branch.clone());
+        }
+        commits_per_branch.insert(queue.branch.clone(), queue.applied);
+        skipped_per_branch.insert(queue.branch.clone(), queue.skipped);
+        if queue.unsigned > 0 {
+            unsigned_per_branch.insert(queue.branch.clone(), queue.unsigned);
+        }
+    }
+    Ok(ApplyOutcome::Complete(ApplySummary {
+        session_id: state.session_id,
+        created_branches: created,
+        commits_per_branch,
+        skipped_per_branch,
+        unsigned_per_branch,
+    }))
+}
+
+/// Create a detached worktree at the branch tip, or at base for branches that do not exist yet.
+pub(crate) fn start_worktree(
+    repo: &Repository,
+    session_id: &str,
+    queue: &mut BranchQueue,
+    base_oid: Oid,
+    wt_path: &Path,
+    sign: bool,
+) -> Result<()> {
+    let target = target_repo(repo, queue)?;
+    let target_path = command_dir(&target);
+    cleanup_worktree(target_path, wt_path)?;
+    if queue.repo.is_some() {
+        prepare_fork(repo, &target, session_id, queue, base_oid)?;
+    }
+    let base = queue_base(queue, base_oid)?;
+    let (mut start_spec, branch_existed) = branch_start_spec(&target, queue.branch_name(), base)?;
+    queue.created = !branch_existed;
+    if let Some(subdir) = queue.options.subdir.as_deref()
+        && !branch_existed
+    {
+        start_spec = subdir::root_commit(&target, base, subdir, sign)?.to_string();
+    }
+    run_git(
+        target_path,
+        [
+            "worktree",
+            "add",
+            "--detach",
+            wt_path.to_str().unwrap(),
+            &start_spec,
+        ],
+    )?;
+    queue.worktree_start = Some(worktree_head(wt_path)?.to_string());
+    Ok(())
+}
+
+/// Repository a queue writes to: the fork named on its target line, or this one.
+pub(crate) fn target_repo(repo: &Repository, queue: &BranchQueue) -> Result<Repository> {
+    let path = queue.repo.as_deref().map_or(repo.path(), Path::new);
+    Repository::open(path).with_context(|| format!("opening repository {}", path.display()))
+}
+
+/// Where git commands for `repo` run: its work tree, or the git dir of a bare repository.
+pub(crate) fn command_dir(repo: &Repository) -> &Path {
+    repo.workdir().unwrap_or(repo.path())
+}
+
+fn queue_base(queue: &BranchQueue, base_oid: Oid) -> Result<Oid> {
+    match &queue.start {
+        Some(start) => Ok(Oid::from_str(start)?),
+        None => Ok(base_oid),
+    }
+}
+
+/// Push the queued commits into a fork under `refs/extract/incoming/<session>/` so they can be
+/// picked there. New branches start from the session base if the fork already had it, else
+/// from the fork's HEAD.
+fn prepare_fork(
+    repo: &Repository,
+    fork: &Repository,
+    session_id: &str,
+    queue: &mut BranchQueue,
+    base_oid: Oid,
+) -> Result<()> {
+    if queue.start.is_none() && fork.find_commit(base_oid).is_err() {
+        let head = fork
+            .head()
+            .and_then(|h| h.peel_to_commit())
+            .with_context(|| format!("{} has no HEAD commit to start from", queue.branch))?;
+        queue.start = Some(head.id().to_string());
+    }
+    let mut args: Vec<String> = vec![
+        "push".into(),
+        "--quiet".into(),
+        "--no-verify".into(),
+        command_dir(fork).to_string_lossy().into_owned(),
+    ];
+    for (i, sha) in queue.commits.iter().enumerate() {
+        args.push(format!("{sha}:{INCOMING_REF_PREFIX}{session_id}/{i}"));
+    }
+    run_git(command_dir(repo), &args)
+        .with_context(|| format!("copying commits into {}", queue.branch))?;
+    Ok(())
+}
+
+/// Session-wide inputs of `apply_branch`.
+struct BranchCtx<'a> {
+    /// Repository the branch is written in: the session's own or a fork.
+    repo: &'a Repository,
+    /// Repository the session runs in, which holds the source commits' notes.
+    source: &'a Repository,
+    notes_refs: &'a [String],
+    options: &'a ApplyOptions,
+    session_id: &'a str,
+    base_oid: Oid,
+}
+
+/// Cherry-pick the remaining commits of one queue inside `wt_path` and move the branch ref.
+fn apply_branch(
+    ctx: &BranchCtx,
+    queue: &mut BranchQueue,
+    wt_path: &Path,
+    resuming: bool,
+) -> Result<BranchStep> {
+    // if resuming from conflict, finish current cherry-pick first; an interrupted
+    // queue stopped between picks, so its first commit has not been applied yet
+    if resuming && !queue.interrupted {
+        // record the user's resolution so rerere can replay it on other targets
+        let _ = run_git(wt_path, RERERE_CONFIG.iter().copied().chain(["rerere"]));
+        let mut kept = true;
+        if let Some(sha) = queue.options.subdir.as_ref().and(queue.commits.first()) {
+            kept = subdir::finish_pick(wt_path, sha, ctx.options.sign)?;
+        } else if cherry_pick_in_progress(wt_path)? {
+            if became_empty(wt_path)? {
+                // stopped by --empty=stop and neither committed nor skipped by hand
+                run_git(wt_path, ["cherry-pick", "--skip"])?;
+                kept = false;
+            } else {
+                continue_cherry_pick(wt_path, ctx.options.sign)?;
             }
-            // whether user already continued or we just did, drop the current commit
-            if !queues[current_idx].commits.is_empty() {
-                queues[current_idx].commits.remove(0);
+        }
+        // `git cherry-pick --skip` or a reset by hand leaves nothing to record
+        if let Some(pick_base) = queue.pick_base.take() {
+            kept = worktree_head(wt_path)?.to_string() != pick_base;
+        }
+        // whether user already continued or we just did, drop the current commit
+        if !queue.commits.is_empty() {
+            let sha = queue.commits.remove(0);
+            if kept {
+                if record_pick(ctx, queue, &sha, wt_path)? {
+                    queue.applied += 1;
+                }
+            } else {
+                queue.last_written = None;
+                queue.skipped += 1;
             }
-            in_conflict = false;
         }
+    }
+    queue.interrupted = false;
+
+    // patches the target already carries (existing branch or an earlier run of the same plan)
+    let mut present = patch_ids_since(ctx.repo, ctx.base_oid, worktree_head(wt_path)?)?;
 
-        // apply remaining commits for this branch
-        let mut commit_count = commits_per_branch.get(&branch).cloned().unwrap_or(0);
-        while let Some(oid_str) = queues[current_idx].commits.first().cloned() {
-            let status = run_git_status(wt_path.as_path(), ["cherry-pick", &oid_str]);
-            match status {
-                Ok(_) => {
-                    queues[current_idx].commits.remove(0);
-                    commit_count += 1;
+    while let Some(oid_str) = queue.commits.first().cloned() {
+        if interrupted() {
+            queue.interrupted = true;
+            return Ok(BranchStep::Interrupted);
+        }
+        let short = &oid_str[..7.min(oid_str.len())];
+        let subdir = queue.options.subdir.as_deref();
+        let pid = patch_id(ctx.repo, Oid::from_str(&oid_str)?, subdir)?;
+        if pid.is_some_and(|pid| present.contains(&pid)) {
+            eprintln!(
+                "note: skipping {short} on {}: already applied",
+                queue.branch
+            );
+            queue.commits.remove(0);
+            queue.last_written = None;
+            queue.skipped += 1;
+            continue;
+        }
+        let mainline = queue.mainline.get(&oid_str).copied();
+        queue.pick_base = Some(worktree_head(wt_path)?.to_string());
+        if let Some(subdir) = subdir {
+            let keep_empty = ctx.options.empty == EmptyMode::Keep;
+            match subdir::pick_into_subdir(
+                wt_path,
+                &oid_str,
+                mainline,
+                subdir,
+                keep_empty,
+                ctx.options.sign,
+            )? {
+                SubdirPick::Applied => {}
+                SubdirPick::Empty if ctx.options.empty == EmptyMode::Stop => {
+                    return Ok(BranchStep::Conflict(format!(
+                        "{short} changes nothing in {subdir} on {}; keep it with git commit --allow-empty -C {oid_str} or run git extract --continue to drop it",
+                        queue.branch
+                    )));
                 }
-                Err(msg) => {
-                    let st = SessionState {
-                        version: 1,
-                        session_id: state_opt
-                            .as_ref()
-                            .map(|s| s.session_id.clone())
-                            .unwrap_or_else(|| Uuid::new_v4().to_string()),
-                        worktree_path: wt_path.to_string_lossy().into_owned(),
-                        current_branch_idx: current_idx,
-                        branch_queues: queues,
-                        in_conflict: true,
-                        base_oid: state_opt
-                            .as_ref()
-                            .map(|s| s.base_oid.clone())
-                            .unwrap_or_else(|| base.base_oid.to_string()),
-                        original_cwd: state_opt
-                            .as_ref()
-                            .map(|s| s.original_cwd.clone())
-                            .unwrap_or_else(|| repo_path.to_string_lossy().into_owned()),
-                    };
-                    return Ok(ApplyOutcome::Conflict(st, msg));
+                SubdirPick::Empty => {
+                    eprintln!(
+                        "note: dropping {short} on {}: it changes nothing in {subdir}",
+                        queue.branch
+                    );
+                    queue.commits.remove(0);
+                    queue.last_written = None;
+                    queue.skipped += 1;
+                    continue;
                 }
+                SubdirPick::Conflict(msg) => return Ok(BranchStep::Conflict(msg)),
+            }
+            if record_pick(ctx, queue, &oid_str, wt_path)? {
+                queue.applied += 1;
             }
+            present.extend(pid);
+            queue.commits.remove(0);
+            continue;
         }
+        let args = cherry_pick_args(ctx.options, &queue.options, &oid_str, mainline);
+        if let Err(msg) = run_git_status(wt_path, args) {
+            if interrupted() {
+                // roll back the half-done pick so the queue head is retried on --continue
+                if cherry_pick_in_progress(wt_path)? {
+                    let _ = run_git(wt_path, ["cherry-pick", "--abort"]);
+                }
+                queue.interrupted = true;
+                return Ok(BranchStep::Interrupted);
+            }
+            if became_empty(wt_path)? {
+                if ctx.options.empty == EmptyMode::Stop {
+                    return Ok(BranchStep::Conflict(format!(
+                        "{short} is empty on {}; keep it with git commit --allow-empty or drop it with git cherry-pick --skip",
+                        queue.branch
+                    )));
+                }
+                run_git(wt_path, ["cherry-pick", "--skip"])?;
+                eprintln!(
+                    "note: dropping {short} on {}: it became empty",
+                    queue.branch
+                );
+                queue.commits.remove(0);
+                queue.last_written = None;
+                queue.skipped += 1;
+                continue;
+            }
+            if !resolved_by_rerere(wt_path)? {
+                return Ok(BranchStep::Conflict(msg));
+            }
+            eprintln!("note: {oid_str} resolved using a recorded resolution");
+            continue_cherry_pick(wt_path, ctx.options.sign)?;
+        }
+        if record_pick(ctx, queue, &oid_str, wt_path)? {
+            queue.applied += 1;
+        }
+        present.extend(pid);
+        queue.commits.remove(0);
+    }
 
-        let head = run_git(
-            repo_path,
-            ["-C", wt_path.to_str().unwrap(), "rev-parse", "HEAD"],
+    if let Some(mode) = queue.options.squash.or(ctx.options.squash) {
+        squash_queue(ctx, queue, mode, wt_path)?;
+    }
+    let oid = worktree_head(wt_path)?;
+    update_checked_out_branch(ctx.repo, ctx.session_id, queue.branch_name(), oid)?;
+    queue.tip = Some(oid.to_string());
+    Ok(BranchStep::Done)
+}
+
+/// Finish a commit just written to the target: apply the author/date/sign-off rewrites and
+/// count a signed source commit whose copy ended up unsigned. Returns false when the pick was
+/// folded into the commit before it, so the branch did not gain a commit.
+fn record_pick(
+    ctx: &BranchCtx,
+    queue: &mut BranchQueue,
+    sha: &str,
+    wt_path: &Path,
+) -> Result<bool> {
+    let fold = queue.fold.get(sha).copied();
+    let folded = match fold {
+        Some(fold) => fold_head(ctx, queue, sha, fold, wt_path)?,
+        None => false,
+    };
+    rewrite_head(ctx.options, ctx.notes_refs, &queue.options, sha, wt_path)?;
+    record_notes(ctx, queue, sha, wt_path)?;
+    let head = worktree_head(wt_path)?;
+    if is_signed(ctx.repo, Oid::from_str(sha)?) && !is_signed(ctx.repo, head) {
+        queue.unsigned += 1;
+    }
+    // a fixup kept as its own commit must not pull later fixups of the same commit into it
+    queue.last_written = (fold.is_none() || folded).then(|| head.to_string());
+    Ok(!folded)
+}
+
+/// Fold the just-picked `fixup!`/`squash!` commit into the commit below it, provided that is
+/// the commit this session wrote for the previous queue entry. When that entry was skipped,
+/// dropped, or the branch already had it, the pick stays a commit of its own.
+fn fold_head(
+    ctx: &BranchCtx,
+    queue: &BranchQueue,
+    sha: &str,
+    fold: Fold,
+    wt_path: &Path,
+) -> Result<bool> {
+    let previous = run_git(wt_path, ["rev-parse", "HEAD~1"])?
+        .trim()
+        .to_string();
+    if queue.last_written.as_deref() != Some(previous.as_str()) {
+        eprintln!(
+            "note: {} has nothing of this session to fold into on {}; keeping it as a commit",
+            &sha[..7.min(sha.len())],
+            queue.branch
+        );
+        return Ok(false);
+    }
+    let mut message = run_git(wt_path, ["log", "-1", "--format=%B", "HEAD~1"])?;
+    if fold == Fold::Squash {
+        let own = run_git(wt_path, ["log", "-1", "--format=%B", "HEAD"])?;
+        // the "squash! <subject>" line only served to find the target
+        let body = match own.split_once('\n') {
+            Some((subject, rest)) if subject.starts_with("squash! ") => rest,
+            None if own.starts_with("squash! ") => "",
+            _ => own.as_str(),
+        };
+        if !body.trim().is_empty() {
+            message = format!("{}\n\n{}", message.trim_end(), body.trim());
+        }
+    }
+    run_git(wt_path, ["reset", "--quiet", "--soft", "HEAD~1"])?;
+    let mut args: Vec<&str> = sign_config(ctx.options.sign).to_vec();
+    args.extend(NO_AMEND_NOTES);
+    args.extend([
+        "commit",
+        "--quiet",
+        "--amend",
+        "--no-verify",
+        "--allow-empty",
+        "-m",
+        &message,
+    ]);
+    run_git(wt_path, args)?;
+    let head = worktree_head(wt_path)?.to_string();
+    copy_notes(
+        ctx.options,
+        ctx.notes_refs,
+        wt_path,
+        &[(&previous, &head)],
+        true,
+    )?;
+    Ok(true)
+}
+
+/// Amend the worktree HEAD for `--reset-author`, `--signoff`, `--committer-date-is-author-date`,
+/// `--record-origin=trailer` and the target's `prefix=`/`trailer=` options; cherry-pick cannot
+/// do most of these itself.
+fn rewrite_head(
+    options: &ApplyOptions,
+    notes_refs: &[String],
+    target: &TargetOptions,
+    sha: &str,
+    wt_path: &Path,
+) -> Result<()> {
+    let trailer = options.record_origin == RecordOrigin::Trailer;
+    if !(options.reset_author
+        || options.signoff
+        || options.committer_date_is_author_date
+        || trailer
+        || target.prefix.is_some()
+        || !target.trailers.is_empty())
+    {
+        return Ok(());
+    }
+    let origin = format!("{ORIGIN_TRAILER}: {sha}");
+    let old = worktree_head(wt_path)?.to_string();
+    let mut args: Vec<&str> = sign_config(options.sign).to_vec();
+    args.extend(NO_AMEND_NOTES);
+    args.extend([
+        "commit",
+        "--quiet",
+        "--amend",
+        "--no-edit",
+        "--no-verify",
+        "--allow-empty",
+    ]);
+    if options.reset_author {
+        args.push("--reset-author");
+    }
+    if options.signoff {
+        args.push("--signoff");
+    }
+    if trailer {
+        args.extend(["--trailer", &origin]);
+    }
+    for t in &target.trailers {
+        args.extend(["--trailer", t]);
+    }
+    let message;
+    if let Some(prefix) = &target.prefix {
+        let current = run_git(wt_path, ["log", "-1", "--format=%B"])?;
+        // a rerun (or a source that already carries it) must not stack the prefix
+        if !current.starts_with(prefix.as_str()) {
+            message = format!("{prefix}{current}");
+            args.extend(["-m", &message]);
+        }
+    }
+    let mut cmd = Command::new("git");
+    cmd.arg("-C").arg(wt_path).args(&args);
+    if options.committer_date_is_author_date {
+        let date = run_git(wt_path, ["log", "-1", "--format=%ad", "--date=raw"])?;
+        cmd.env("GIT_COMMITTER_DATE", date.trim());
+        // --reset-author would take the current time; keep the original date with the new name
+        cmd.env("GIT_AUTHOR_DATE", date.trim());
+    }
+    let output = cmd.output().context("amending extracted commit")?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow!("git commit --amend failed: {stderr}"));
+    }
+    let new = worktree_head(wt_path)?.to_string();
+    copy_notes(options, notes_refs, wt_path, &[(&old, &new)], true)
+}
+
+/// Collapse the commits written since the worktree was created into one commit titled by the
+/// first subject and listing all of them; trailers of the individual commits are kept and
+/// notes are combined like `git rebase` squashes do.
+fn squash_queue(
+    ctx: &BranchCtx,
+    queue: &mut BranchQueue,
+    mode: SquashMode,
+    wt_path: &Path,
+) -> Result<()> {
+    let Some(start) = queue.worktree_start.as_deref() else {
+        return Ok(());
+    };
+    let head = worktree_head(wt_path)?.to_string();
+    let range = format!("{start}..{head}");
+    let commits: Vec<String> = run_git(wt_path, ["rev-list", "--reverse", &range])?
+        .lines()
+        .map(str::to_string)
+        .collect();
+    if commits.len() < 2 {
+        return Ok(());
+    }
+    let subjects = run_git(wt_path, ["log", "--reverse", "--format=%s", &range])?;
+    let trailers = run_git(
+        wt_path,
+        [
+            "log",
+            "--reverse",
+            "--format=%(trailers:only,unfold)",
+            &range,
+        ],
+    )?;
+    let mut message = format!("{}\n\n", subjects.lines().next().unwrap_or_default());
+    for subject in subjects.lines() {
+        message.push_str(&format!("* {subject}\n"));
+    }
+    let mut seen = std::collections::HashSet::new();
+    let trailers: Vec<&str> = trailers
+        .lines()
+        .filter(|t| !t.is_empty() && seen.insert(*t))
+        .collect();
+    if !trailers.is_empty() {
+        message.push('\n');
+        message.push_str(&trailers.join("\n"));
+        message.push('\n');
+    }
+    let msg_path =
+        wt_path.join(run_git(wt_path, ["rev-parse", "--git-path", "EXTRACT_SQUASH_MSG"])?.trim());
+    std::fs::write(&msg_path, &message).context("writing squash message")?;
+
+    // the squashed commit keeps the author and date of the first one
+    let first = &commits[0];
+    let author = run_git(wt_path, ["log", "-1", "--format=%an <%ae>", first])?;
+    let date = run_git(wt_path, ["log", "-1", "--format=%ad", "--date=raw", first])?;
+
+    run_git(wt_path, ["reset", "--quiet", "--soft", start])?;
+    let mut cmd = Command::new("git");
+    cmd.arg("-C")
+        .arg(wt_path)
+        .args(sign_config(ctx.options.sign))
+        .args(["commit", "--quiet", "--no-verify", "--allow-empty"])
+        .arg(format!("--author={}", author.trim()))
+        .arg(format!("--date={}", date.trim()))
+        .arg("-F")
+        .arg(&msg_path);
+    if mode == SquashMode::Edit {
+        cmd.arg("--edit");
+        if let Some(editor) = &ctx.options.editor {
+            cmd.env("GIT_EDITOR", editor);
+        }
+    }
+    let status = cmd.status().context("committing squash");
+    let _ = std::fs::remove_file(&msg_path);
+    if !status.is_ok_and(|s| s.success()) {
+        // put the individual commits back so the session can be retried or aborted
+        run_git(wt_path, ["reset", "--quiet", "--soft", &head])?;
+        return Err(anyhow!("squashing the commits for {} failed", queue.branch));
+    }
+
+    // combine the notes of the squashed commits, including the --record-origin note
+    let squashed = worktree_head(wt_path)?.to_string();
+    let pairs: Vec<(&str, &str)> = commits
+        .iter()
+        .map(|c| (c.as_str(), squashed.as_str()))
+        .collect();
+    copy_notes(ctx.options, ctx.notes_refs, wt_path, &pairs, true)?;
+    queue.applied = 1;
+
+    // the kept trailers already carry each commit's sign-off and origin; --reset-author and
+    // --committer-date-is-author-date still apply to the new commit
+    let rewrite = ApplyOptions {
+        signoff: false,
+        record_origin: RecordOrigin::None,
+        ..ctx.options.clone()
+    };
+    rewrite_head(
+        &rewrite,
+        ctx.notes_refs,
+        &TargetOptions::default(),
+        first,
+        wt_path,
+    )
+}
+
+/// Write the `--record-origin=notes` note and copy the source's notes (`notes.rewriteRef`).
+fn record_notes(ctx: &BranchCtx, queue: &BranchQueue, sha: &str, wt_path: &Path) -> Result<()> {
+    if ctx.options.record_origin == RecordOrigin::Notes {
+        let note = format!(
+            "{ORIGIN_TRAILER}: {sha}\nExtract-Session: {}",
+            ctx.session_id
+        );
+        run_git(
+            wt_path,
+            [
+                "notes",
+                "--ref",
+                ORIGIN_NOTES_REF,
+                "append",
+                "-m",
+                &note,
+                "HEAD",
+            ],
         )?;
-        let trimmed = head.trim();
-        let oid = Oid::from_str(trimmed).context("parse resulting HEAD")?;
-        update_branch_ref(repo, &branch, oid)?;
-        commits_per_branch.insert(branch.clone(), commit_count);
+    }
+    let head = worktree_head(wt_path)?;
+    if queue.repo.is_some() {
+        return carry_notes(ctx, Oid::from_str(sha)?, head);
+    }
+    copy_notes(
+        ctx.options,
+        ctx.notes_refs,
+        wt_path,
+        &[(sha, &head.to_string())],
+        false,
+    )
+}
+
+/// Refs in `GIT_NOTES_REWRITE_REF`, else `notes.rewriteRef`, of the repository the session runs
+/// in; they apply to fork targets as well.
+fn notes_rewrite_refs(repo: &Repository) -> Vec<String> {
+    let mut refs: Vec<String> = match std::env::var("GIT_NOTES_REWRITE_REF") {
+        Ok(refs) => refs.split(':').map(str::to_string).collect(),
+        Err(_) => run_git(
+            command_dir(repo),
+            ["config", "--get-all", "notes.rewriteRef"],
+        )
+        .unwrap_or_default()
+        .lines()
+        .map(str::to_string)
+        .collect(),
+    };
+    refs.retain(|r| !r.is_empty());
+    refs
+}
 
-        cleanup_worktree(repo_path, &wt_path)?;
-        current_idx += 1;
-        state_opt = None; // after first resume step, treat subsequent branches fresh
+/// `copy_notes` for a fork target, whose repository lacks the source commit's notes: read them
+/// here and write them onto the copy in the fork, combined as `notes.rewriteMode` says.
+fn carry_notes(ctx: &BranchCtx, sha: Oid, new: Oid) -> Result<()> {
+    let mode = match std::env::var("GIT_NOTES_REWRITE_MODE") {
+        Ok(mode) => mode,
+        Err(_) => ctx
+            .source
+            .config()?
+            .get_string("notes.rewriteMode")
+            .unwrap_or_else(|_| "concatenate".to_string()),
+    };
+    let mut refs = Vec::new();
+    for pattern in ctx.notes_refs {
+        if pattern.contains('*') {
+            for reference in ctx.source.references_glob(pattern)? {
+                refs.extend(reference?.name().map(str::to_string));
+            }
+        } else {
+            refs.push(pattern.clone());
+        }
     }
+    let signature = ctx
+        .repo
+        .signature()
+        .or_else(|_| git2::Signature::now("git-extract", "git-extract@localhost"))?;
+    for notes_ref in &refs {
+        let Some(note) = ctx
+            .source
+            .find_note(Some(notes_ref), sha)
+            .ok()
+            .and_then(|n| n.message().map(str::to_string))
+        else {
+            continue;
+        };
+        let existing = ctx
+            .repo
+            .find_note(Some(notes_ref), new)
+            .ok()
+            .and_then(|n| n.message().map(str::to_string));
+        let combined = match (existing, mode.as_str()) {
+            (None, _) | (Some(_), "overwrite") => note,
+            (Some(_), "ignore") => continue,
+            (Some(old), "cat_sort_uniq") => {
+                let mut lines: Vec<&str> = old.lines().chain(note.lines()).collect();
+                lines.sort_unstable();
+                lines.dedup();
+                lines.iter().map(|l| format!("{l}\n")).collect()
+            }
+            (Some(old), _) => format!("{}\n\n{note}", old.strip_suffix('\n').unwrap_or(&old)),
+        };
+        ctx.repo
+            .note(
+                &signature,
+                &signature,
+                Some(notes_ref),
+                new,
+                &combined,
+                true,
+            )
+            .with_context(|| {
+                format!(
+                    "copying {notes_ref} note of {sha} into {}",
+                    ctx.repo.path().display()
+                )
+            })?;
+    }
+    Ok(())
+}
 
-    Ok(ApplyOutcome::Complete(ApplySummary {
-        created_branches: created,
-        commits_per_branch,
-    }))
+/// Copy notes along `(old, new)` pairs with `git notes copy --for-rewrite`, for `notes_refs`
+/// plus, with `with_origin`, the `--record-origin=notes` ref; `notes.rewriteMode` decides how
+/// notes are combined.
+fn copy_notes(
+    options: &ApplyOptions,
+    notes_refs: &[String],
+    wt_path: &Path,
+    pairs: &[(&str, &str)],
+    with_origin: bool,
+) -> Result<()> {
+    let mut refs = notes_refs.to_vec();
+    if with_origin && options.record_origin == RecordOrigin::Notes {
+        refs.push(ORIGIN_NOTES_REF.to_string());
+    }
+    // without a rewrite ref git exits before reading the pairs
+    if refs.is_empty() {
+        return Ok(());
+    }
+    let mut copy = Command::new("git")
+        .arg("-C")
+        .arg(wt_path)
+        .args(["notes", "copy", "--for-rewrite=extract", "--stdin"])
+        .env("GIT_NOTES_REWRITE_REF", refs.join(":"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("copying notes")?;
+    if let Some(mut stdin) = copy.stdin.take() {
+        for (old, new) in pairs {
+            writeln!(stdin, "{old} {new}")?;
+        }
+    }
+    let output = copy.wait_with_output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow!("git notes copy failed: {stderr}"));
+    }
+    Ok(())
+}
+
+fn worktree_head(wt_path: &Path) -> Result<Oid> {
+    let head = run_git(
+        wt_path,
+        ["-C", wt_path.to_str().unwrap(), "rev-parse", "HEAD"],
+    )?;
+    Oid::from_str(head.trim()).context("parse resulting HEAD")
+}
+
+/// Build `git cherry-pick` arguments; target-line settings take precedence over global flags.
+fn cherry_pick_args(
+    options: &ApplyOptions,
+    target: &TargetOptions,
+    sha: &str,
+    mainline: Option<u32>,
+) -> Vec<String> {
+    let mut args: Vec<String> = RERERE_CONFIG
+        .iter()
+        .chain(&sign_config(options.sign))
+        .map(|s| s.to_string())
+        .collect();
+    if let Some(style) = target
+        .conflict_style
+        .as_ref()
+        .or(options.conflict_style.as_ref())
+    {
+        args.push("-c".into());
+        args.push(format!("merge.conflictStyle={style}"));
+    }
+    args.push("cherry-pick".into());
+    if options.empty == EmptyMode::Keep {
+        args.push("--allow-empty".into());
+        args.push("--keep-redundant-commits".into());
+    }
+    if let Some(strategy) = target.strategy.as_ref().or(options.strategy.as_ref()) {
+        args.push(format!("--strategy={strategy}"));
+    }
+    let strategy_options = if target.strategy_options.is_empty() {
+        &options.strategy_options
+    } else {
+        &target.strategy_options
+    };
+    for opt in strategy_options {
+        args.push(format!("-X{opt}"));
+    }
+    if let Some(n) = mainline {
+        args.push(format!("--mainline={n}"));
+    }
+    args.push(sha.to_string());
+    args
 }
+
 fn branch_start_spec(repo: &Repository, branch: &str, base_oid: Oid) -> Result<(String, bool)> {
     if let Ok(existing) = repo.find_branch(branch, BranchType::Local) {
         let _target = existing
@@ -219,13 +1245,122 @@ fn branch_start_spec(repo: &Repository, branch: &str, base_oid: Oid) -> Result<(
     Ok((base_oid.to_string(), false))
 }
 
-fn update_branch_ref(repo: &Repository, branch: &str, target: Oid) -> Result<()> {
+pub(crate) fn branch_tip(repo: &Repository, branch: &str) -> Option<String> {
+    repo.find_reference(&format!("refs/heads/{branch}"))
+        .ok()
+        .and_then(|r| r.target())
+        .map(|oid| oid.to_string())
+}
+
+/// Move `refs/heads/<branch>`, keeping the previous tip under `refs/extract/backup/<session>/`
+/// until the session completes.
+fn update_branch_ref(repo: &Repository, session_id: &str, branch: &str, target: Oid) -> Result<()> {
     let refname = format!("refs/heads/{branch}");
+    if let Ok(existing) = repo.find_reference(&refname)
+        && let Some(old) = existing.target()
+    {
+        let backup = format!("{BACKUP_REF_PREFIX}{session_id}/{branch}");
+        if repo.find_reference(&backup).is_err() {
+            repo.reference(&backup, old, false, "git-extract backup")?;
+        }
+    }
     repo.reference(&refname, target, true, "git-extract update")?;
     Ok(())
 }
 
-fn run_git<S: AsRef<str>>(repo_path: &Path, args: impl IntoIterator<Item = S>) -> Result<String> {
+/// Worktree of `repo` (main or linked) that has `branch` checked out.
+fn checked_out_in(repo: &Repository, branch: &str) -> Result<Option<PathBuf>> {
+    let list = run_git(command_dir(repo), ["worktree", "list", "--porcelain"])?;
+    let wanted = format!("branch refs/heads/{branch}");
+    let mut path = None;
+    for line in list.lines() {
+        if let Some(p) = line.strip_prefix("worktree ") {
+            path = Some(PathBuf::from(p));
+        } else if line == wanted {
+            // a worktree whose directory is gone has nothing to keep in sync
+            return Ok(path.filter(|p| p.exists()));
+        }
+    }
+    Ok(None)
+}
+
+fn has_local_changes(wt_path: &Path) -> Result<bool> {
+    let status = run_git(wt_path, ["status", "--porcelain", "--untracked-files=no"])?;
+    Ok(!status.trim().is_empty())
+}
+
+/// `update_branch_ref`, carrying along a worktree that has the branch checked out so its index
+/// and files do not show the extracted commits as reverted. A worktree with local changes
+/// (`--force`, or dirtied during a pause) is left alone with a warning.
+fn update_checked_out_branch(
+    repo: &Repository,
+    session_id: &str,
+    branch: &str,
+    new: Oid,
+) -> Result<()> {
+    let checked_out = match checked_out_in(repo, branch)? {
+        Some(wt) => Some((has_local_changes(&wt)?, wt)),
+        None => None,
+    };
+    let old = branch_tip(repo, branch);
+    update_branch_ref(repo, session_id, branch, new)?;
+    let Some((dirty, wt)) = checked_out else {
+        return Ok(());
+    };
+    let Some(old) = old.filter(|old| *old != new.to_string()) else {
+        return Ok(());
+    };
+    let short = &old[..7];
+    if dirty {
+        eprintln!(
+            "warning: {branch} is checked out in {} with local changes; its index and files still match {short}",
+            wt.display()
+        );
+        return Ok(());
+    }
+    let new = new.to_string();
+    if let Err(msg) = run_git_status(&wt, ["read-tree", "-m", "-u", old.as_str(), new.as_str()]) {
+        eprintln!(
+            "warning: {branch} is checked out in {} and could not be updated: {}",
+            wt.display(),
+            msg.trim()
+        );
+    } else {
+        println!("updated worktree {} to the new {branch}", wt.display());
+    }
+    Ok(())
+}
+
+pub(crate) fn remove_backup_refs(repo: &Repository, session_id: &str) -> Result<()> {
+    for prefix in [BACKUP_REF_PREFIX, INCOMING_REF_PREFIX] {
+        let glob = format!("{prefix}{session_id}/*");
+        for reference in repo.references_glob(&glob)? {
+            reference?.delete()?;
+        }
+    }
+    Ok(())
+}
+
+/// Drop the refs a finished or aborted session kept, here and in every fork it wrote to.
+fn remove_session_refs(repo: &Repository, state: &SessionState) -> Result<()> {
+    remove_backup_refs(repo, &state.session_id)?;
+    let mut forks: Vec<&str> = state
+        .branch_queues
+        .iter()
+        .filter_map(|q| q.repo.as_deref())
+        .collect();
+    forks.sort();
+    forks.dedup();
+    for fork in forks {
+        remove_backup_refs(&Repository::open(fork)?, &state.session_id)?;
+    }
+    remove_imports(repo, &state.sources)
+}
+
+pub(crate) fn run_git<S: AsRef<str>>(
+    repo_path: &Path,
+    args: impl IntoIterator<Item = S>,
+) -> Result<String> {
     let mut cmd = Command::new("git");
     cmd.arg("-C").arg(repo_path);
     for a in args {
@@ -239,7 +1374,7 @@ fn run_git<S: AsRef<str>>(repo_path: &Path, args: impl IntoIterator<Item = S>) -
     Ok(String::from_utf8_lossy(&output.stdout).to_string())
 }
 
-fn run_git_status<S: AsRef<str>>(
+pub(crate) fn run_git_status<S: AsRef<str>>(
     wt_path: &Path,
     args: impl IntoIterator<Item = S>,
 ) -> Result<(), String> {
@@ -256,7 +1391,7 @@ fn run_git_status<S: AsRef<str>>(
     Ok(())
 }
 
-fn cleanup_worktree(repo_path: &Path, wt_path: &Path) -> Result<()> {
+pub(crate) fn cleanup_worktree(repo_path: &Path, wt_path: &Path) -> Result<()> {
     if wt_path.exists() {
         let _ = run_git(
             repo_path,
@@ -264,21 +1399,35 @@ fn cleanup_worktree(repo_path: &Path, wt_path: &Path) -> Result<()> {
         );
         if wt_path.exists() {
             std::fs::remove_dir_all(wt_path).ok();
+            // drop the registration `worktree remove` could not take with it
+            let _ = run_git(repo_path, ["worktree", "prune"]);
         }
     }
     Ok(())
 }
 
-fn ensure_worktree_clean(wt_path: &Path, allow_dirty: bool) -> Result<()> {
+fn ensure_worktree_clean(wt_path: &Path, allow_dirty: bool, subdir_pick: bool) -> Result<()> {
     if allow_dirty {
         return Ok(());
     }
+    if !unmerged_paths(wt_path)?.is_empty() {
+        return Err(anyhow!(
+            "worktree still has unresolved conflicts; resolve and stage them before --continue"
+        ));
+    }
     let status = run_git(
         wt_path,
         ["-C", wt_path.to_str().unwrap(), "status", "--porcelain"],
     )?;
-    if !status.trim().is_empty() {
-        // allow staged/non-conflict? To keep simple, require clean
+    // staged resolutions are committed by the pending cherry-pick and untracked files (such as
+    // the `.orig` backups git mergetool keeps by default) are left out of it; anything else
+    // must be clean
+    let pending = subdir_pick || cherry_pick_in_progress(wt_path)?;
+    let dirty = status.lines().any(|line| {
+        let bytes = line.as_bytes();
+        !(pending && bytes.len() > 1 && (bytes[0] == b'?' || bytes[1] == b' '))
+    });
+    if dirty {
         return Err(anyhow!(
             "worktree is not clean; resolve conflicts and stage changes before --continue"
         ));
@@ -286,7 +1435,7 @@ fn ensure_worktree_clean(wt_path: &Path, allow_dirty: bool) -> Result<()> {
     Ok(())
 }
 
-fn cherry_pick_in_progress(wt_path: &Path) -> Result<bool> {
+pub(crate) fn cherry_pick_in_progress(wt_path: &Path) -> Result<bool> {
     let path = run_git(
         wt_path,
         [
@@ -300,3 +1449,37 @@ fn cherry_pick_in_progress(wt_path: &Path) -> Result<bool> {
     let p = PathBuf::from(path.trim());
     Ok(p.exists())
 }
+
+/// True when a stopped cherry-pick left nothing to commit (its change is already on the target).
+fn became_empty(wt_path: &Path) -> Result<bool> {
+    if !cherry_pick_in_progress(wt_path)? || !unmerged_paths(wt_path)?.is_empty() {
+        return Ok(false);
+    }
+    Ok(run_git_status(wt_path, ["diff", "--cached", "--quiet"]).is_ok())
+}
+
+/// True when a stopped cherry-pick had all its conflicts resolved (and staged) by rerere.
+fn resolved_by_rerere(wt_path: &Path) -> Result<bool> {
+    if !cherry_pick_in_progress(wt_path)? || !unmerged_paths(wt_path)?.is_empty() {
+        return Ok(false);
+    }
+    let staged = run_git_status(wt_path, ["diff", "--cached", "--quiet"]).is_err();
+    Ok(staged)
+}
+
+fn continue_cherry_pick(wt_path: &Path, sign: bool) -> Result<()> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(wt_path)
+        .args(RERERE_CONFIG)
+        .args(sign_config(sign))
+        .args(["cherry-pick", "--continue"])
+        .env("GIT_EDITOR", "true")
+        .output()
+        .context("continuing cherry-pick")?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow!("git cherry-pick --continue failed: {stderr}"));
+    }
+    Ok(())
+}
@@ -1,6 +1,8 @@
 use assert_cmd::prelude::*;
+use predicates::str::contains;
 use serde_json::Value;
 use std::fs;
+use std::os::unix::fs::PermissionsExt;
 use std::path::{Path, PathBuf};
 use std::process::Command;
 use tempfile::TempDir;
@@ -26,32 +28,42 @@ fn git_out(repo: &Path, args: &[&str]) -> String {
     String::from_utf8_lossy(&out.stdout).trim().to_string()
 }
 
+fn state_file(repo: &Path) -> Option<PathBuf> {
+    fs::read_dir(repo.join(".git"))
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .find(|p| {
+            let name = p.file_name().unwrap().to_string_lossy();
+            name.starts_with("extract-state-") && name.ends_with(".json")
+        })
+}
+
 fn setup_conflict_repo() -> TempDir {
     let tmp = TempDir::new().unwrap();
     let repo = tmp.path();
-    git(&repo, &["init"]);
-    git(&repo, &["config", "user.email", "test@example.com"]);
-    git(&repo, &["config", "user.name", "Tester"]);
+    git(repo, &["init"]);
+    git(repo, &["config", "user.email", "test@example.com"]);
+    git(repo, &["config", "user.name", "Tester"]);
 
     fs::write(repo.join("file.txt"), "base\n").unwrap();
-    git(&repo, &["add", "file.txt"]);
-    git(&repo, &["commit", "-m", "base"]);
+    git(repo, &["add", "file.txt"]);
+    git(repo, &["commit", "-m", "base"]);
 
     // main change diverging
     fs::write(repo.join("file.txt"), "main-change\n").unwrap();
-    git(&repo, &["add", "file.txt"]);
-    git(&repo, &["commit", "-m", "main change"]);
+    git(repo, &["add", "file.txt"]);
+    git(repo, &["commit", "-m", "main change"]);
 
     // work branch from base (before main change)
-    let base_commit = git_out(&repo, &["rev-list", "--max-parents=0", "HEAD"]);
-    git(&repo, &["checkout", "-b", "work", &base_commit]);
+    let base_commit = git_out(repo, &["rev-list", "--max-parents=0", "HEAD"]);
+    git(repo, &["checkout", "-b", "work", &base_commit]);
 
     fs::write(repo.join("file.txt"), "work1\n").unwrap();
-    git(&repo, &["add", "file.txt"]);
-    git(&repo, &["commit", "-m", "work1"]);
+    git(repo, &["add", "file.txt"]);
+    git(repo, &["commit", "-m", "work1"]);
     fs::write(repo.join("file.txt"), "work2\n").unwrap();
-    git(&repo, &["add", "file.txt"]);
-    git(&repo, &["commit", "-m", "work2"]);
+    git(repo, &["add", "file.txt"]);
+    git(repo, &["commit", "-m", "work2"]);
 
     tmp
 }
@@ -62,8 +74,8 @@ fn conflict_then_continue() {
     let repo = tmp.path();
 
     // routing file assigns both commits to feature
-    let c1 = git_out(&repo, &["rev-parse", "--short", "HEAD~1"]);
-    let c2 = git_out(&repo, &["rev-parse", "--short", "HEAD"]);
+    let c1 = git_out(repo, &["rev-parse", "--short", "HEAD~1"]);
+    let c2 = git_out(repo, &["rev-parse", "--short", "HEAD"]);
     let routing = repo.join("routing.txt");
     fs::write(
         &routing,
@@ -72,8 +84,8 @@ fn conflict_then_continue() {
     .unwrap();
 
     let bin = assert_cmd::cargo::cargo_bin!("git-extract");
-    let assert = Command::new(&bin)
-        .current_dir(&repo)
+    let assert = Command::new(bin)
+        .current_dir(repo)
         .args([
             "--routing-file",
             routing.to_str().unwrap(),
@@ -84,8 +96,7 @@ fn conflict_then_continue() {
     // conflict expected but process exits 0 (prints instruction)
     assert.success();
 
-    let state_path = repo.join(".git").join("extract-state.json");
-    assert!(state_path.exists());
+    let state_path = state_file(repo).expect("state file written");
     let state: Value = serde_json::from_str(&fs::read_to_string(&state_path).unwrap()).unwrap();
     let wt_path = PathBuf::from(state["worktree_path"].as_str().unwrap());
     assert!(wt_path.exists());
@@ -95,15 +106,15 @@ fn conflict_then_continue() {
     git(&wt_path, &["add", "file.txt"]);
     git(&wt_path, &["cherry-pick", "--continue"]);
 
-    Command::new(&bin)
-        .current_dir(&repo)
+    Command::new(bin)
+        .current_dir(repo)
         .args(["--continue", "--allow-dirty"])
         .assert()
         .success();
 
     // state removed and branch updated
     assert!(!state_path.exists());
-    let log = git_out(&repo, &["log", "--oneline", "feature"]);
+    let log = git_out(repo, &["log", "--oneline", "feature"]);
     assert!(log.contains("work1"));
     assert!(log.contains("work2"));
 }
@@ -113,8 +124,8 @@ fn conflict_abort() {
     let tmp = setup_conflict_repo();
     let repo = tmp.path();
 
-    let c1 = git_out(&repo, &["rev-parse", "--short", "HEAD~1"]);
-    let c2 = git_out(&repo, &["rev-parse", "--short", "HEAD"]);
+    let c1 = git_out(repo, &["rev-parse", "--short", "HEAD~1"]);
+    let c2 = git_out(repo, &["rev-parse", "--short", "HEAD"]);
     let routing = repo.join("routing.txt");
     fs::write(
         &routing,
@@ -123,8 +134,8 @@ fn conflict_abort() {
     .unwrap();
 
     let bin = assert_cmd::cargo::cargo_bin!("git-extract");
-    Command::new(&bin)
-        .current_dir(&repo)
+    Command::new(bin)
+        .current_dir(repo)
         .args([
             "--routing-file",
             routing.to_str().unwrap(),
@@ -134,16 +145,15 @@ fn conflict_abort() {
         .assert()
         .success();
 
-    let state_path = repo.join(".git").join("extract-state.json");
-    assert!(state_path.exists());
+    let state_path = state_file(repo).expect("state file written");
     let wt_path = {
         let v: Value = serde_json::from_str(&fs::read_to_string(&state_path).unwrap()).unwrap();
         PathBuf::from(v["worktree_path"].as_str().unwrap())
     };
     assert!(wt_path.exists());
 
-    Command::new(&bin)
-        .current_dir(&repo)
+    Command::new(bin)
+        .current_dir(repo)
         .args(["--abort", "--allow-dirty"])
         .assert()
         .success();
@@ -153,9 +163,579 @@ fn conflict_abort() {
     // feature branch should not exist
     let status = Command::new("git")
         .arg("-C")
-        .arg(&repo)
+        .arg(repo)
         .args(["show-ref", "refs/heads/feature"])
         .status()
         .unwrap();
     assert!(!status.success());
 }
+
+#[test]
+fn keep_going_parks_conflicts_and_continues() {
+    let tmp = setup_conflict_repo();
+    let repo = tmp.path();
+    fs::write(repo.join("other.txt"), "other\n").unwrap();
+    git(repo, &["add", "other.txt"]);
+    git(repo, &["commit", "-m", "other"]);
+
+    let c1 = git_out(repo, &["rev-parse", "--short", "HEAD~2"]);
+    let c2 = git_out(repo, &["rev-parse", "--short", "HEAD~1"]);
+    let c3 = git_out(repo, &["rev-parse", "--short", "HEAD"]);
+    let routing = repo.join("routing.txt");
+    fs::write(
+        &routing,
+        format!(
+            "target 1 feature\ntarget 2 tidy\n1 {c1} work1\ncurrent {c2} work2\n2 {c3} other\n"
+        ),
+    )
+    .unwrap();
+
+    let bin = assert_cmd::cargo::cargo_bin!("git-extract");
+    Command::new(bin)
+        .current_dir(repo)
+        .args([
+            "--routing-file",
+            routing.to_str().unwrap(),
+            "--keep-going",
+            "--allow-dirty",
+        ])
+        .assert()
+        .success();
+
+    // the conflict on feature did not block tidy
+    let state_path = state_file(repo).expect("state file written");
+    let tidy_log = git_out(repo, &["log", "--oneline", "tidy"]);
+    assert!(tidy_log.contains("other"));
+
+    let state: Value = serde_json::from_str(&fs::read_to_string(&state_path).unwrap()).unwrap();
+    let parked = state["parked"].as_array().unwrap();
+    assert_eq!(parked.len(), 1);
+    let wt_path = PathBuf::from(parked[0]["worktree_path"].as_str().unwrap());
+    assert!(wt_path.exists());
+
+    fs::write(wt_path.join("file.txt"), "work1\n").unwrap();
+    git(&wt_path, &["add", "file.txt"]);
+    git(
+        &wt_path,
+        &["-c", "core.editor=true", "cherry-pick", "--continue"],
+    );
+
+    Command::new(bin)
+        .current_dir(repo)
+        .args(["--continue", "--allow-dirty"])
+        .assert()
+        .success();
+
+    assert!(!state_path.exists());
+    assert!(!wt_path.exists());
+    let log = git_out(repo, &["log", "--oneline", "feature"]);
+    assert!(log.contains("work1"));
+}
+
+#[test]
+fn on_conflict_shell_continues_after_exit() {
+    let tmp = setup_conflict_repo();
+    let repo = tmp.path();
+
+    let c1 = git_out(repo, &["rev-parse", "--short", "HEAD~1"]);
+    let c2 = git_out(repo, &["rev-parse", "--short", "HEAD"]);
+    let routing = repo.join("routing.txt");
+    fs::write(
+        &routing,
+        format!("target 1 feature\n1 {c1} work1\n1 {c2} work2\n"),
+    )
+    .unwrap();
+
+    // stand-in for an interactive shell: resolve the conflict and exit
+    let scripts = TempDir::new().unwrap();
+    let shell = scripts.path().join("resolve.sh");
+    fs::write(
+        &shell,
+        "#!/bin/sh\necho work1 > file.txt\ngit add file.txt\n",
+    )
+    .unwrap();
+    fs::set_permissions(&shell, fs::Permissions::from_mode(0o755)).unwrap();
+
+    let bin = assert_cmd::cargo::cargo_bin!("git-extract");
+    Command::new(bin)
+        .current_dir(repo)
+        .env("SHELL", &shell)
+        .args([
+            "--routing-file",
+            routing.to_str().unwrap(),
+            "--on-conflict",
+            "shell",
+            "--allow-dirty",
+        ])
+        .assert()
+        .success();
+
+    assert!(state_file(repo).is_none());
+    let log = git_out(repo, &["log", "--oneline", "feature"]);
+    assert!(log.contains("work1"));
+    assert!(log.contains("work2"));
+}
+
+#[test]
+fn on_conflict_mergetool_continues_with_backup_files() {
+    let tmp = setup_conflict_repo();
+    let repo = tmp.path();
+
+    let c1 = git_out(repo, &["rev-parse", "--short", "HEAD~1"]);
+    let c2 = git_out(repo, &["rev-parse", "--short", "HEAD"]);
+    let routing = repo.join(".git").join("routing.txt");
+    fs::write(
+        &routing,
+        format!("target 1 feature\n1 {c1} work1\n1 {c2} work2\n"),
+    )
+    .unwrap();
+
+    // scripted merge tool; mergetool.keepBackup stays at its default and leaves file.txt.orig
+    git(repo, &["config", "merge.tool", "fake"]);
+    git(
+        repo,
+        &["config", "mergetool.fake.cmd", "echo work1 > \"$MERGED\""],
+    );
+    git(repo, &["config", "mergetool.fake.trustExitCode", "true"]);
+    git(repo, &["config", "mergetool.prompt", "false"]);
+
+    let bin = assert_cmd::cargo::cargo_bin!("git-extract");
+    Command::new(bin)
+        .current_dir(repo)
+        .args([
+            "--routing-file",
+            routing.to_str().unwrap(),
+            "--on-conflict",
+            "mergetool",
+        ])
+        .assert()
+        .success();
+
+    assert!(state_file(repo).is_none());
+    let log = git_out(repo, &["log", "--oneline", "feature"]);
+    assert!(log.contains("work1"));
+    assert!(log.contains("work2"));
+}
+
+#[test]
+fn conflict_explained_against_routing_plan() {
+    let tmp = setup_conflict_repo();
+    let repo = tmp.path();
+
+    // work2 builds on work1, but work1 stays on the current branch
+    let c1 = git_out(repo, &["rev-parse", "--short", "HEAD~1"]);
+    let c2 = git_out(repo, &["rev-parse", "--short", "HEAD"]);
+    let routing = repo.join("routing.txt");
+    fs::write(
+        &routing,
+        format!("target 1 feature\ncurrent {c1} work1\n1 {c2} work2\n"),
+    )
+    .unwrap();
+
+    let bin = assert_cmd::cargo::cargo_bin!("git-extract");
+    Command::new(bin)
+        .current_dir(repo)
+        .args(["--routing-file", routing.to_str().unwrap(), "--allow-dirty"])
+        .assert()
+        .success()
+        .stdout(contains(format!(
+            "conflict in file.txt likely depends on {c1} (routed to current)"
+        )));
+}
+
+#[test]
+fn rerere_replays_resolution_on_rerun() {
+    let tmp = setup_conflict_repo();
+    let repo = tmp.path();
+
+    let c2 = git_out(repo, &["rev-parse", "--short", "HEAD"]);
+    let c1 = git_out(repo, &["rev-parse", "--short", "HEAD~1"]);
+    let routing = repo.join("routing.txt");
+    fs::write(
+        &routing,
+        format!("target 1 feature\n1 {c1} work1\ncurrent {c2} work2\n"),
+    )
+    .unwrap();
+
+    let bin = assert_cmd::cargo::cargo_bin!("git-extract");
+    let run = || {
+        Command::new(bin)
+            .current_dir(repo)
+            .args(["--routing-file", routing.to_str().unwrap(), "--allow-dirty"])
+            .assert()
+            .success();
+    };
+    run();
+
+    let state_path = state_file(repo).expect("state file written");
+    let state: Value = serde_json::from_str(&fs::read_to_string(&state_path).unwrap()).unwrap();
+    let wt_path = PathBuf::from(state["worktree_path"].as_str().unwrap());
+    fs::write(wt_path.join("file.txt"), "resolved\n").unwrap();
+    git(&wt_path, &["add", "file.txt"]);
+    Command::new(bin)
+        .current_dir(repo)
+        .args(["--continue", "--allow-dirty"])
+        .assert()
+        .success();
+    assert!(!state_path.exists());
+
+    // the same conflict on a re-run is resolved from rr-cache without stopping
+    git(repo, &["branch", "-D", "feature"]);
+    run();
+    assert!(state_file(repo).is_none());
+    let content = git_out(repo, &["show", "feature:file.txt"]);
+    assert_eq!(content, "resolved");
+}
+
+#[test]
+fn concurrent_sessions_are_isolated() {
+    let tmp = setup_conflict_repo();
+    let repo = tmp.path();
+
+    let c1 = git_out(repo, &["rev-parse", "--short", "HEAD~1"]);
+    let c2 = git_out(repo, &["rev-parse", "--short", "HEAD"]);
+    let bin = assert_cmd::cargo::cargo_bin!("git-extract");
+    let mut sessions = Vec::new();
+    for branch in ["feature-a", "feature-b"] {
+        let routing = repo.join(format!("{branch}.txt"));
+        fs::write(
+            &routing,
+            format!("target 1 {branch}\n1 {c1} work1\ncurrent {c2} work2\n"),
+        )
+        .unwrap();
+        let assert = Command::new(bin)
+            .current_dir(repo)
+            .args(["--routing-file", routing.to_str().unwrap(), "--allow-dirty"])
+            .assert()
+            .success();
+        let stdout = String::from_utf8_lossy(&assert.get_output().stdout).to_string();
+        let id = stdout
+            .lines()
+            .find_map(|l| l.strip_prefix("Session: "))
+            .expect("session id printed")
+            .to_string();
+        assert!(repo.join(".git").join(format!("extract-wt-{id}")).exists());
+        sessions.push(id);
+    }
+    assert_ne!(sessions[0], sessions[1]);
+
+    // ambiguous without --session
+    Command::new(bin)
+        .current_dir(repo)
+        .args(["--abort"])
+        .assert()
+        .failure();
+
+    // a held lock blocks the session, and its state file is not even migrated
+    let lock = repo
+        .join(".git")
+        .join(format!("extract-state-{}.lock", sessions[0]));
+    fs::write(&lock, "12345\n").unwrap();
+    let state_path = repo
+        .join(".git")
+        .join(format!("extract-state-{}.json", sessions[0]));
+    let mut state: Value = serde_json::from_str(&fs::read_to_string(&state_path).unwrap()).unwrap();
+    state["version"] = Value::from(1);
+    fs::write(&state_path, state.to_string()).unwrap();
+    Command::new(bin)
+        .current_dir(repo)
+        .args(["--abort", "--session", sessions[0].as_str()])
+        .assert()
+        .failure()
+        .stderr(contains("in use"));
+    assert_eq!(fs::read_to_string(&state_path).unwrap(), state.to_string());
+    fs::remove_file(&lock).unwrap();
+
+    Command::new(bin)
+        .current_dir(repo)
+        .args(["--abort", "--session", sessions[0].as_str()])
+        .assert()
+        .success();
+    Command::new(bin)
+        .current_dir(repo)
+        .args(["--abort"])
+        .assert()
+        .success();
+    assert!(state_file(repo).is_none());
+}
+
+#[test]
+fn repair_recovers_crashed_session() {
+    let tmp = setup_conflict_repo();
+    let repo = tmp.path();
+
+    let c1 = git_out(repo, &["rev-parse", "--short", "HEAD~1"]);
+    let c2 = git_out(repo, &["rev-parse", "--short", "HEAD"]);
+    let routing = repo.join("routing.txt");
+    fs::write(
+        &routing,
+        format!("target 1 feature\n1 {c1} work1\n1 {c2} work2\n"),
+    )
+    .unwrap();
+
+    let bin = assert_cmd::cargo::cargo_bin!("git-extract");
+    Command::new(bin)
+        .current_dir(repo)
+        .args(["--routing-file", routing.to_str().unwrap(), "--allow-dirty"])
+        .assert()
+        .success();
+    let state_path = state_file(repo).expect("state file written");
+    let state: Value = serde_json::from_str(&fs::read_to_string(&state_path).unwrap()).unwrap();
+    let session = state["session_id"].as_str().unwrap().to_string();
+    let wt_path = PathBuf::from(state["worktree_path"].as_str().unwrap());
+
+    // simulate a killed process: lock left behind, worktree gone, plus leftovers
+    let lock = repo
+        .join(".git")
+        .join(format!("extract-state-{session}.lock"));
+    fs::write(&lock, "999999999\n").unwrap();
+    fs::remove_dir_all(&wt_path).unwrap();
+    let orphan = repo.join(".git").join("extract-wt");
+    git(
+        repo,
+        &[
+            "worktree",
+            "add",
+            "--detach",
+            orphan.to_str().unwrap(),
+            "HEAD",
+        ],
+    );
+    git(
+        repo,
+        &[
+            "update-ref",
+            "refs/extract/backup/gone-session/feature",
+            "HEAD",
+        ],
+    );
+
+    Command::new(bin)
+        .current_dir(repo)
+        .arg("--repair")
+        .assert()
+        .success()
+        .stdout(contains("removed stale lock"))
+        .stdout(contains("recreated worktree for feature"))
+        .stdout(contains("removed orphaned worktree"))
+        .stdout(contains("deleted dangling backup ref"));
+
+    assert!(!lock.exists());
+    assert!(wt_path.exists());
+    assert!(!orphan.exists());
+    let refs = git_out(repo, &["for-each-ref", "refs/extract/"]);
+    assert!(refs.is_empty());
+
+    // the rebuilt session is usable again
+    Command::new(bin)
+        .current_dir(repo)
+        .args(["--continue", "--allow-dirty"])
+        .assert()
+        .success()
+        .stdout(contains("Conflict encountered"));
+    Command::new(bin)
+        .current_dir(repo)
+        .arg("--abort")
+        .assert()
+        .success();
+    assert!(state_file(repo).is_none());
+}
+
+#[test]
+fn repair_cleans_fork_worktrees_and_spares_running_imports() {
+    let tmp = setup_conflict_repo();
+    let repo = tmp.path();
+    let fork_dir = TempDir::new().unwrap();
+    let fork = fork_dir.path();
+    git(
+        fork,
+        &[
+            "clone",
+            "-q",
+            repo.to_str().unwrap(),
+            fork.to_str().unwrap(),
+        ],
+    );
+    let fork_target = format!("../{}:feature", fork.file_name().unwrap().to_string_lossy());
+    let fork_worktrees = || git_out(fork, &["worktree", "list", "--porcelain"]);
+
+    let c1 = git_out(repo, &["rev-parse", "--short", "HEAD~1"]);
+    let c2 = git_out(repo, &["rev-parse", "--short", "HEAD"]);
+    let routing = repo.join(".git").join("routing.txt");
+    fs::write(
+        &routing,
+        format!("target 1 {fork_target}\n1 {c1} work1\ncurrent {c2} work2\n"),
+    )
+    .unwrap();
+    let bin = assert_cmd::cargo::cargo_bin!("git-extract");
+    Command::new(bin)
+        .current_dir(repo)
+        .args(["--routing-file", routing.to_str().unwrap()])
+        .assert()
+        .success()
+        .stdout(contains("Conflict encountered"));
+    let state_path = state_file(repo).expect("state file written");
+    let mut state: Value = serde_json::from_str(&fs::read_to_string(&state_path).unwrap()).unwrap();
+    let wt_path = PathBuf::from(state["worktree_path"].as_str().unwrap());
+    assert!(fork_worktrees().contains("extract-wt-"));
+
+    // killed between picks: the half-applied worktree is registered in the fork
+    state["in_conflict"] = Value::Bool(false);
+    fs::write(&state_path, state.to_string()).unwrap();
+    // a worktree of a session whose state file is gone, also registered in the fork
+    let orphan = repo
+        .join(".git")
+        .join("extract-wt-00000000-0000-0000-0000-000000000000");
+    git(
+        fork,
+        &[
+            "worktree",
+            "add",
+            "--detach",
+            orphan.to_str().unwrap(),
+            "HEAD",
+        ],
+    );
+    // import worktrees: one of a running --from-mbox, one left by a dead process
+    let running = repo
+        .join(".git")
+        .join(format!("extract-import-{}-busy", std::process::id()));
+    let dead = repo.join(".git").join("extract-import-999999999-gone");
+    for wt in [&running, &dead] {
+        git(
+            repo,
+            &["worktree", "add", "--detach", wt.to_str().unwrap(), "HEAD"],
+        );
+    }
+
+    Command::new(bin)
+        .current_dir(repo)
+        .arg("--repair")
+        .assert()
+        .success()
+        .stdout(contains("removed half-applied worktree"))
+        .stdout(contains("removed orphaned worktree"));
+    assert!(!wt_path.exists());
+    assert!(!orphan.exists());
+    assert!(!fork_worktrees().contains("extract-wt-"));
+    assert!(running.exists());
+    assert!(!dead.exists());
+    assert!(!git_out(repo, &["worktree", "list"]).contains("extract-import-999999999"));
+}
+
+#[test]
+fn continue_refuses_moved_target_and_newer_state() {
+    let tmp = setup_conflict_repo();
+    let repo = tmp.path();
+
+    let c1 = git_out(repo, &["rev-parse", "--short", "HEAD~1"]);
+    let c2 = git_out(repo, &["rev-parse", "--short", "HEAD"]);
+    let routing = repo.join("routing.txt");
+    fs::write(
+        &routing,
+        format!("target 1 feature\n1 {c1} work1\n1 {c2} work2\n"),
+    )
+    .unwrap();
+
+    let bin = assert_cmd::cargo::cargo_bin!("git-extract");
+    Command::new(bin)
+        .current_dir(repo)
+        .args(["--routing-file", routing.to_str().unwrap(), "--allow-dirty"])
+        .assert()
+        .success();
+    let state_path = state_file(repo).expect("state file written");
+    let original = fs::read_to_string(&state_path).unwrap();
+
+    let mut state: Value = serde_json::from_str(&original).unwrap();
+    state["version"] = Value::from(99);
+    fs::write(&state_path, state.to_string()).unwrap();
+    Command::new(bin)
+        .current_dir(repo)
+        .args(["--continue", "--allow-dirty"])
+        .assert()
+        .failure()
+        .stderr(contains("has version 99"));
+    fs::write(&state_path, &original).unwrap();
+
+    // someone else creates the target while the session is paused
+    git(repo, &["branch", "feature", "HEAD"]);
+    Command::new(bin)
+        .current_dir(repo)
+        .args(["--continue", "--allow-dirty"])
+        .assert()
+        .failure()
+        .stderr(contains("target feature moved since the session paused"));
+
+    git(repo, &["branch", "-D", "feature"]);
+    Command::new(bin)
+        .current_dir(repo)
+        .args(["--abort"])
+        .assert()
+        .success();
+}
+
+#[test]
+fn legacy_state_file_is_migrated() {
+    let tmp = setup_conflict_repo();
+    let repo = tmp.path();
+
+    let c1 = git_out(repo, &["rev-parse", "--short", "HEAD~1"]);
+    let c2 = git_out(repo, &["rev-parse", "--short", "HEAD"]);
+    let routing = repo.join("routing.txt");
+    fs::write(
+        &routing,
+        format!("target 1 feature\n1 {c1} work1\ndrop {c2} work2\n"),
+    )
+    .unwrap();
+
+    let bin = assert_cmd::cargo::cargo_bin!("git-extract");
+    Command::new(bin)
+        .current_dir(repo)
+        .args(["--routing-file", routing.to_str().unwrap(), "--allow-dirty"])
+        .assert()
+        .success();
+
+    // rewrite the paused session into the old single-file layout
+    let state_path = state_file(repo).expect("state file written");
+    let state: Value = serde_json::from_str(&fs::read_to_string(&state_path).unwrap()).unwrap();
+    let wt_path = PathBuf::from(state["worktree_path"].as_str().unwrap());
+    let legacy_wt = repo.join(".git").join("extract-wt");
+    git(
+        repo,
+        &[
+            "worktree",
+            "move",
+            wt_path.to_str().unwrap(),
+            legacy_wt.to_str().unwrap(),
+        ],
+    );
+    let legacy = serde_json::json!({
+        "version": 1,
+        "session_id": state["session_id"],
+        "worktree_path": legacy_wt.to_str().unwrap(),
+        "current_branch_idx": 0,
+        "branch_queues": [{ "branch": "feature", "commits": state["branch_queues"][0]["commits"] }],
+        "in_conflict": true,
+        "base_oid": state["base_oid"],
+        "original_cwd": state["original_cwd"],
+    });
+    fs::remove_file(&state_path).unwrap();
+    fs::write(repo.join(".git/extract-state.json"), legacy.to_string()).unwrap();
+
+    fs::write(legacy_wt.join("file.txt"), "work1\n").unwrap();
+    git(&legacy_wt, &["add", "file.txt"]);
+
+    Command::new(bin)
+        .current_dir(repo)
+        .args(["--continue", "--allow-dirty"])
+        .assert()
+        .success()
+        .stderr(contains("migrated legacy extract state"))
+        .stderr(contains("predates ref tracking"));
+
+    assert!(!repo.join(".git/extract-state.json").exists());
+    assert!(!legacy_wt.exists());
+    assert!(state_file(repo).is_none());
+    let log = git_out(repo, &["log", "--oneline", "feature"]);
+    assert!(log.contains("work1"));
+}
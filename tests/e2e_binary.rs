@@ -1,4 +1,5 @@
 use assert_cmd::prelude::*;
+use predicates::prelude::PredicateBooleanExt;
 use predicates::str::contains;
 use std::fs;
 use std::path::{Path, PathBuf};
@@ -26,6 +27,16 @@ fn git_out(repo: &Path, args: &[&str]) -> String {
     String::from_utf8_lossy(&out.stdout).trim().to_string()
 }
 
+fn state_file(repo: &Path) -> Option<PathBuf> {
+    fs::read_dir(repo.join(".git"))
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .find(|p| {
+            let name = p.file_name().unwrap().to_string_lossy();
+            name.starts_with("extract-state-") && name.ends_with(".json")
+        })
+}
+
 fn init_repo() -> TempDir {
     let tmp = TempDir::new().unwrap();
     let repo = tmp.path();
@@ -35,6 +46,28 @@ fn init_repo() -> TempDir {
     tmp
 }
 
+/// `init_repo` with a `root` commit adding README; returns the repository and that commit.
+fn init_repo_with_root() -> (TempDir, String) {
+    let tmp = init_repo();
+    let repo = tmp.path();
+    fs::write(repo.join("README"), "readme\n").unwrap();
+    git(repo, &["add", "README"]);
+    git(repo, &["commit", "-q", "-m", "root"]);
+    let root = git_out(repo, &["rev-parse", "HEAD"]);
+    (tmp, root)
+}
+
+/// Write `routing` to `.git/routing.txt` and run git-extract in `repo` on it with `flags`.
+fn run_extract(repo: &Path, routing: &str, flags: &[&str]) -> assert_cmd::assert::Assert {
+    let path = repo.join(".git").join("routing.txt");
+    fs::write(&path, routing).unwrap();
+    Command::new(assert_cmd::cargo::cargo_bin!("git-extract"))
+        .current_dir(repo)
+        .args(flags)
+        .args(["--routing-file", path.to_str().unwrap()])
+        .assert()
+}
+
 #[test]
 fn e2e_happy_path_routing() {
     let tmp = init_repo();
@@ -77,7 +110,7 @@ fn e2e_happy_path_routing() {
     assert!(feature_log.contains("c1"));
     let main_log = git_out(repo, &["log", "--oneline", "HEAD"]);
     assert!(main_log.contains("c2"));
-    assert!(!repo.join(".git").join("extract-state.json").exists());
+    assert!(state_file(repo).is_none());
 }
 
 #[test]
@@ -118,7 +151,7 @@ fn e2e_dry_run_creates_nothing() {
         .status()
         .unwrap();
     assert!(!status.success());
-    assert!(!repo.join(".git").join("extract-state.json").exists());
+    assert!(state_file(repo).is_none());
 }
 
 #[test]
@@ -150,7 +183,7 @@ fn e2e_conflict_continue() {
     fs::write(&routing, format!("target 1 feature\n1 {c1} work1\n")).unwrap();
 
     let bin = assert_cmd::cargo::cargo_bin!("git-extract");
-    Command::new(&bin)
+    Command::new(bin)
         .current_dir(repo)
         .args([
             "--routing-file",
@@ -161,8 +194,7 @@ fn e2e_conflict_continue() {
         .assert()
         .success();
 
-    let state_path = repo.join(".git").join("extract-state.json");
-    assert!(state_path.exists());
+    let state_path = state_file(repo).expect("state file written");
     let wt_path: PathBuf = {
         let v: serde_json::Value =
             serde_json::from_str(&fs::read_to_string(&state_path).unwrap()).unwrap();
@@ -175,7 +207,7 @@ fn e2e_conflict_continue() {
     git(&wt_path, &["add", "file.txt"]);
     git(&wt_path, &["cherry-pick", "--continue"]);
 
-    Command::new(&bin)
+    Command::new(bin)
         .current_dir(repo)
         .args(["--continue", "--allow-dirty"])
         .assert()
@@ -210,7 +242,7 @@ fn e2e_conflict_abort() {
     fs::write(&routing, format!("target 1 feature\n1 {c1} work1\n")).unwrap();
 
     let bin = assert_cmd::cargo::cargo_bin!("git-extract");
-    Command::new(&bin)
+    Command::new(bin)
         .current_dir(repo)
         .args([
             "--routing-file",
@@ -221,9 +253,8 @@ fn e2e_conflict_abort() {
         .assert()
         .success();
 
-    let state_path = repo.join(".git").join("extract-state.json");
-    assert!(state_path.exists());
-    Command::new(&bin)
+    let state_path = state_file(repo).expect("state file written");
+    Command::new(bin)
         .current_dir(repo)
         .args(["--abort", "--allow-dirty"])
         .assert()
@@ -242,15 +273,1118 @@ fn e2e_conflict_abort() {
 #[test]
 fn e2e_help_outputs() {
     let bin = assert_cmd::cargo::cargo_bin!("git-extract");
-    Command::new(&bin)
+    Command::new(bin)
         .arg("--help")
         .assert()
         .success()
         .stdout(contains("git extract"));
 
-    Command::new(&bin)
+    Command::new(bin)
         .arg("help")
         .assert()
         .success()
         .stdout(contains("git extract"));
 }
+
+#[test]
+fn e2e_target_strategy_option_avoids_whitespace_conflict() {
+    let tmp = init_repo();
+    let repo = tmp.path();
+
+    fs::write(repo.join("file.txt"), "a\nb\n").unwrap();
+    git(repo, &["add", "file.txt"]);
+    git(repo, &["commit", "-m", "base"]);
+
+    // main only changes whitespace on the line the work branch edits
+    fs::write(repo.join("file.txt"), "a  \nb\n").unwrap();
+    git(repo, &["commit", "-am", "whitespace"]);
+
+    let base = git_out(repo, &["rev-list", "--max-parents=0", "HEAD"]);
+    git(repo, &["checkout", "-b", "work", base.as_str()]);
+    fs::write(repo.join("file.txt"), "A\nb\n").unwrap();
+    git(repo, &["commit", "-am", "work1"]);
+
+    let c1 = git_out(repo, &["rev-parse", "--short", "HEAD"]);
+    let routing = repo.join("routing.txt");
+    fs::write(
+        &routing,
+        format!("target 1 feature strategy-option=ignore-space-change\n1 {c1} work1\n"),
+    )
+    .unwrap();
+
+    Command::new(assert_cmd::cargo::cargo_bin!("git-extract"))
+        .current_dir(repo)
+        .args(["--routing-file", routing.to_str().unwrap(), "--allow-dirty"])
+        .assert()
+        .success();
+
+    assert!(state_file(repo).is_none());
+    assert_eq!(git_out(repo, &["show", "feature:file.txt"]), "A\nb");
+}
+
+#[test]
+fn e2e_skips_already_applied_and_empty_commits() {
+    let tmp = init_repo();
+    let repo = tmp.path();
+
+    fs::write(repo.join("file.txt"), "base\n").unwrap();
+    fs::write(repo.join("other.txt"), "base\n").unwrap();
+    git(repo, &["add", "."]);
+    git(repo, &["commit", "-m", "base"]);
+    let base = git_out(repo, &["rev-parse", "HEAD"]);
+
+    // main already carries the change of w1, made independently
+    fs::write(repo.join("other.txt"), "x\n").unwrap();
+    git(repo, &["commit", "-am", "same change on main"]);
+    let main = git_out(repo, &["rev-parse", "HEAD"]);
+
+    git(repo, &["checkout", "-b", "work", base.as_str()]);
+    fs::write(repo.join("other.txt"), "x\n").unwrap();
+    git(repo, &["commit", "-am", "w1"]);
+    fs::write(repo.join("file.txt"), "w2\n").unwrap();
+    git(repo, &["commit", "-am", "w2"]);
+    let w1 = git_out(repo, &["rev-parse", "--short", "HEAD~1"]);
+    let w2 = git_out(repo, &["rev-parse", "--short", "HEAD"]);
+
+    // feature already has w2 from an earlier extraction
+    git(repo, &["branch", "feature", main.as_str()]);
+    git(repo, &["checkout", "-q", "feature"]);
+    git(repo, &["cherry-pick", w2.as_str()]);
+    git(repo, &["checkout", "-q", "work"]);
+    let feature_tip = git_out(repo, &["rev-parse", "feature"]);
+
+    let routing = repo.join("routing.txt");
+    fs::write(
+        &routing,
+        format!("target 1 feature\n1 {w1} w1\n1 {w2} w2\n"),
+    )
+    .unwrap();
+    let bin = assert_cmd::cargo::cargo_bin!("git-extract");
+    Command::new(bin)
+        .current_dir(repo)
+        .args([
+            "--routing-file",
+            routing.to_str().unwrap(),
+            "--base",
+            main.as_str(),
+            "--allow-dirty",
+        ])
+        .assert()
+        .success()
+        .stderr(predicates::str::contains(format!(
+            "dropping {w1} on feature: it became empty"
+        )))
+        .stderr(predicates::str::contains(format!(
+            "skipping {w2} on feature: already applied"
+        )))
+        .stdout(predicates::str::contains("feature: 0 commits (2 skipped)"));
+    assert_eq!(git_out(repo, &["rev-parse", "feature"]), feature_tip);
+
+    // --empty=stop pauses on the empty commit; continuing without a decision drops it
+    fs::write(&routing, format!("target 1 fresh\n1 {w1} w1\n1 {w2} w2\n")).unwrap();
+    Command::new(bin)
+        .current_dir(repo)
+        .args([
+            "--routing-file",
+            routing.to_str().unwrap(),
+            "--base",
+            main.as_str(),
+            "--empty",
+            "stop",
+            "--allow-dirty",
+        ])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains(format!("{w1} is empty on fresh")));
+    Command::new(bin)
+        .current_dir(repo)
+        .args(["--continue", "--allow-dirty"])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("fresh: 1 commits (1 skipped)"));
+    assert_eq!(git_out(repo, &["log", "--format=%s", "-1", "fresh"]), "w2");
+    assert!(state_file(repo).is_none());
+
+    // following the advice and dropping it by hand counts it as skipped, not as a pick
+    fs::write(
+        &routing,
+        format!("target 1 dropped\n1 {w1} w1\n1 {w2} w2\n"),
+    )
+    .unwrap();
+    Command::new(bin)
+        .current_dir(repo)
+        .args([
+            "--routing-file",
+            routing.to_str().unwrap(),
+            "--base",
+            main.as_str(),
+            "--empty",
+            "stop",
+            "--record-origin",
+            "trailer",
+            "--allow-dirty",
+        ])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains(format!(
+            "{w1} is empty on dropped"
+        )));
+    let worktrees = git_out(repo, &["worktree", "list", "--porcelain"]);
+    let wt = worktrees
+        .lines()
+        .filter_map(|l| l.strip_prefix("worktree "))
+        .find(|p| p.contains("extract-wt-"))
+        .unwrap();
+    git(Path::new(wt), &["cherry-pick", "--skip"]);
+    Command::new(bin)
+        .current_dir(repo)
+        .args(["--continue", "--allow-dirty"])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("dropped: 1 commits (1 skipped)"));
+    assert_eq!(git_out(repo, &["rev-parse", "dropped~1"]), main);
+    let message = git_out(repo, &["log", "--format=%B", "-1", "dropped"]);
+    assert_eq!(message.matches("Extracted-From:").count(), 1);
+    assert!(state_file(repo).is_none());
+}
+
+#[test]
+fn e2e_merge_commits_flatten_mainline_and_drop() {
+    let tmp = init_repo();
+    let repo = tmp.path();
+
+    fs::write(repo.join("file.txt"), "base\n").unwrap();
+    git(repo, &["add", "file.txt"]);
+    git(repo, &["commit", "-m", "base"]);
+    let base = git_out(repo, &["rev-parse", "HEAD"]);
+
+    git(repo, &["checkout", "-q", "-b", "work"]);
+    fs::write(repo.join("w1.txt"), "w1\n").unwrap();
+    git(repo, &["add", "w1.txt"]);
+    git(repo, &["commit", "-m", "w1"]);
+
+    git(repo, &["checkout", "-q", "-b", "side"]);
+    for name in ["s1", "s2"] {
+        fs::write(repo.join(format!("{name}.txt")), name).unwrap();
+        git(repo, &["add", "."]);
+        git(repo, &["commit", "-m", name]);
+    }
+    git(repo, &["checkout", "-q", "work"]);
+    git(repo, &["merge", "--no-ff", "-m", "merge side", "side"]);
+    let m = git_out(repo, &["rev-parse", "--short", "HEAD"]);
+
+    // "merge main into work": everything it brings in is already on the base
+    git(repo, &["checkout", "-q", "-b", "upstream", base.as_str()]);
+    fs::write(repo.join("up.txt"), "up\n").unwrap();
+    git(repo, &["add", "up.txt"]);
+    git(repo, &["commit", "-m", "upstream"]);
+    let upstream = git_out(repo, &["rev-parse", "HEAD"]);
+    git(repo, &["checkout", "-q", "work"]);
+    git(
+        repo,
+        &["merge", "--no-ff", "-m", "merge upstream", "upstream"],
+    );
+    let m2 = git_out(repo, &["rev-parse", "--short", "HEAD"]);
+
+    fs::write(repo.join("w2.txt"), "w2\n").unwrap();
+    git(repo, &["add", "w2.txt"]);
+    git(repo, &["commit", "-m", "w2"]);
+    let w1 = git_out(repo, &["rev-parse", "--short", "HEAD~3"]);
+    let w2 = git_out(repo, &["rev-parse", "--short", "HEAD"]);
+
+    let routing = repo.join("routing.txt");
+    fs::write(
+        &routing,
+        format!(
+            "target 1 flat\ntarget 2 squashed\n1 {w1} w1\n2^1 {m} merge side\ndrop {m2} merge upstream\n1 {w2} w2\n"
+        ),
+    )
+    .unwrap();
+    Command::new(assert_cmd::cargo::cargo_bin!("git-extract"))
+        .current_dir(repo)
+        .args([
+            "--routing-file",
+            routing.to_str().unwrap(),
+            "--base",
+            upstream.as_str(),
+            "--allow-dirty",
+        ])
+        .assert()
+        .success();
+
+    assert_eq!(
+        git_out(repo, &["log", "--format=%s", &format!("{upstream}..flat")]),
+        "w2\nw1"
+    );
+    assert_eq!(
+        git_out(
+            repo,
+            &["log", "--format=%s", &format!("{upstream}..squashed")]
+        ),
+        "merge side"
+    );
+    assert_eq!(git_out(repo, &["show", "squashed:s2.txt"]), "s2");
+
+    // a bare destination flattens the merge into the commits it brought in
+    fs::write(
+        &routing,
+        format!("drop {w1} w1\nflat2 {m} merge side\ndrop {m2} merge upstream\ndrop {w2} w2\n"),
+    )
+    .unwrap();
+    Command::new(assert_cmd::cargo::cargo_bin!("git-extract"))
+        .current_dir(repo)
+        .args([
+            "--routing-file",
+            routing.to_str().unwrap(),
+            "--base",
+            upstream.as_str(),
+            "--allow-dirty",
+        ])
+        .assert()
+        .success();
+    assert_eq!(
+        git_out(repo, &["log", "--format=%s", &format!("{upstream}..flat2")]),
+        "s2\ns1"
+    );
+}
+
+#[test]
+fn e2e_source_and_range_without_checkout() {
+    let tmp = init_repo();
+    let repo = tmp.path();
+
+    fs::write(repo.join("file.txt"), "base\n").unwrap();
+    git(repo, &["add", "file.txt"]);
+    git(repo, &["commit", "-m", "base"]);
+    let base = git_out(repo, &["rev-parse", "HEAD"]);
+
+    git(repo, &["checkout", "-q", "-b", "work"]);
+    for name in ["w1", "w2", "w3"] {
+        fs::write(repo.join(format!("{name}.txt")), name).unwrap();
+        git(repo, &["add", "."]);
+        git(repo, &["commit", "-m", name]);
+    }
+    let w1 = git_out(repo, &["rev-parse", "--short", "HEAD~2"]);
+    let w2 = git_out(repo, &["rev-parse", "--short", "HEAD~1"]);
+    let w3 = git_out(repo, &["rev-parse", "--short", "HEAD"]);
+
+    // someone else's branch, extracted from a detached HEAD
+    git(repo, &["checkout", "-q", "--detach", base.as_str()]);
+    let routing = repo.join("routing.txt");
+    fs::write(
+        &routing,
+        format!("target 1 picked\n1 {w1} w1\ncurrent {w2} w2\n1 {w3} w3\n"),
+    )
+    .unwrap();
+    let bin = assert_cmd::cargo::cargo_bin!("git-extract");
+    Command::new(bin)
+        .current_dir(repo)
+        .args(["--source", "work", "--base", base.as_str()])
+        .args(["--routing-file", routing.to_str().unwrap(), "--allow-dirty"])
+        .assert()
+        .success();
+    assert_eq!(
+        git_out(repo, &["log", "--format=%s", &format!("{base}..picked")]),
+        "w3\nw1"
+    );
+    assert!(state_file(repo).is_none());
+
+    fs::write(&routing, format!("target 1 ranged\n1 {w3} w3\n")).unwrap();
+    Command::new(bin)
+        .current_dir(repo)
+        .args(["--range", "work~1..work", "--base", base.as_str()])
+        .args(["--routing-file", routing.to_str().unwrap(), "--allow-dirty"])
+        .assert()
+        .success();
+    assert_eq!(
+        git_out(repo, &["log", "--format=%s", &format!("{base}..ranged")]),
+        "w3"
+    );
+
+    Command::new(bin)
+        .current_dir(repo)
+        .args(["--routing-file", routing.to_str().unwrap(), "--allow-dirty"])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("detached HEAD"));
+}
+
+#[test]
+fn e2e_multiple_sources_in_one_session() {
+    let tmp = init_repo();
+    let repo = tmp.path();
+
+    fs::write(repo.join("file.txt"), "base\n").unwrap();
+    git(repo, &["add", "file.txt"]);
+    git(repo, &["commit", "-m", "base"]);
+    let base = git_out(repo, &["rev-parse", "HEAD"]);
+
+    let mut shas = Vec::new();
+    for branch in ["half-a", "half-b"] {
+        git(repo, &["checkout", "-q", "-b", branch, base.as_str()]);
+        for n in 1..=2 {
+            let name = format!("{branch}-{n}");
+            fs::write(repo.join(format!("{name}.txt")), &name).unwrap();
+            git(repo, &["add", "."]);
+            git(repo, &["commit", "-m", &name]);
+            shas.push(git_out(repo, &["rev-parse", "--short", "HEAD"]));
+        }
+    }
+    git(repo, &["checkout", "-q", "--detach", base.as_str()]);
+
+    // consolidate two half-done branches into two clean ones
+    let routing = repo.join("routing.txt");
+    fs::write(
+        &routing,
+        format!(
+            "target 1 clean-x\ntarget 2 clean-y\n# from half-a\n1 {} a1\n2 {} a2\n# from half-b\n1 {} b1\ndrop {} b2\n",
+            shas[0], shas[1], shas[2], shas[3]
+        ),
+    )
+    .unwrap();
+    Command::new(assert_cmd::cargo::cargo_bin!("git-extract"))
+        .current_dir(repo)
+        .args(["--source", "half-a", "--source", "half-b"])
+        .args(["--base", base.as_str(), "--allow-dirty"])
+        .args(["--routing-file", routing.to_str().unwrap()])
+        .assert()
+        .success();
+
+    assert_eq!(
+        git_out(repo, &["log", "--format=%s", &format!("{base}..clean-x")]),
+        "half-b-1\nhalf-a-1"
+    );
+    assert_eq!(
+        git_out(repo, &["log", "--format=%s", &format!("{base}..clean-y")]),
+        "half-a-2"
+    );
+}
+
+#[test]
+fn e2e_from_mbox_routes_imported_patches() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let tmp = init_repo();
+    let repo = tmp.path();
+
+    fs::write(repo.join("file.txt"), "base\n").unwrap();
+    git(repo, &["add", "file.txt"]);
+    git(repo, &["commit", "-m", "base"]);
+    let base = git_out(repo, &["rev-parse", "HEAD"]);
+
+    // a contributor's series, received by mail
+    for name in ["fix-a", "fix-b"] {
+        fs::write(repo.join(format!("{name}.txt")), name).unwrap();
+        git(repo, &["add", "."]);
+        git(repo, &["commit", "-m", name]);
+    }
+    let patches = repo.join(".git").join("patches");
+    git(
+        repo,
+        &["format-patch", "-q", "-o", patches.to_str().unwrap(), &base],
+    );
+    let mbox = repo.join(".git").join("series.mbox");
+    let out = Command::new("git")
+        .arg("-C")
+        .arg(repo)
+        .args(["format-patch", "--stdout", &base])
+        .output()
+        .unwrap();
+    fs::write(&mbox, out.stdout).unwrap();
+    git(repo, &["reset", "-q", "--hard", &base]);
+
+    // route the first patch to one topic and the second to another
+    let editor = repo.join(".git").join("route.sh");
+    fs::write(
+        &editor,
+        "#!/bin/sh\nsed -i -e '0,/^current /s//topic-a /' -e 's/^current /topic-b /' \"$1\"\n",
+    )
+    .unwrap();
+    fs::set_permissions(&editor, fs::Permissions::from_mode(0o755)).unwrap();
+
+    let bin = assert_cmd::cargo::cargo_bin!("git-extract");
+    Command::new(bin)
+        .current_dir(repo)
+        .args(["--from-mbox", ".git/patches"])
+        .args(["--editor", editor.to_str().unwrap()])
+        .assert()
+        .success();
+    assert_eq!(
+        git_out(repo, &["log", "--format=%s", &format!("{base}..topic-a")]),
+        "fix-a"
+    );
+    assert_eq!(
+        git_out(repo, &["log", "--format=%s", &format!("{base}..topic-b")]),
+        "fix-b"
+    );
+    assert_eq!(
+        git_out(repo, &["log", "--format=%an", "-1", "topic-b"]),
+        "Tester"
+    );
+    assert!(git_out(repo, &["for-each-ref", "refs/extract/"]).is_empty());
+
+    // a single mbox file works the same; dry runs leave nothing behind
+    Command::new(bin)
+        .current_dir(repo)
+        .args(["--from-mbox", mbox.to_str().unwrap(), "--dry-run"])
+        .args(["--editor", editor.to_str().unwrap()])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("topic-a: 1 commits"));
+    Command::new(bin)
+        .current_dir(repo)
+        .args(["--from-mbox", ".git/series.mbox", "--dry-run"])
+        .args(["--editor", editor.to_str().unwrap()])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("topic-b: 1 commits"));
+    assert!(git_out(repo, &["for-each-ref", "refs/extract/"]).is_empty());
+}
+
+#[test]
+fn e2e_target_in_another_local_repository() {
+    let tmp = TempDir::new().unwrap();
+    let internal = tmp.path().join("internal");
+    let public = tmp.path().join("public");
+    for repo in [&internal, &public] {
+        fs::create_dir(repo).unwrap();
+        git(repo, &["init", "-q"]);
+        git(repo, &["config", "user.email", "test@example.com"]);
+        git(repo, &["config", "user.name", "Tester"]);
+        fs::write(repo.join("README"), "readme\n").unwrap();
+        git(repo, &["add", "README"]);
+        git(repo, &["commit", "-m", "root"]);
+    }
+    let public_root = git_out(&public, &["rev-parse", "HEAD"]);
+
+    fs::write(internal.join("secret.txt"), "internal only\n").unwrap();
+    git(&internal, &["add", "secret.txt"]);
+    git(&internal, &["commit", "-m", "internal change"]);
+    fs::write(internal.join("lib.txt"), "shared\n").unwrap();
+    git(&internal, &["add", "lib.txt"]);
+    git(&internal, &["commit", "-m", "shared change"]);
+    let root = git_out(&internal, &["rev-list", "--max-parents=0", "HEAD"]);
+    let secret = git_out(&internal, &["rev-parse", "--short", "HEAD~1"]);
+    let shared = git_out(&internal, &["rev-parse", "--short", "HEAD"]);
+    // notes live in the repository the session runs in and must follow the commit
+    git(
+        &internal,
+        &["config", "notes.rewriteRef", "refs/notes/commits"],
+    );
+    git(&internal, &["notes", "add", "-m", "reviewed", "HEAD"]);
+
+    let routing = format!(
+        "target 1 ../public:feature\ncurrent {secret} internal change\n1 {shared} shared change\n"
+    );
+    run_extract(&internal, &routing, &["--base", &root])
+        .success()
+        .stdout(predicates::str::contains("../public:feature: 1 commits"));
+
+    // the unrelated public history is the start, and only the routed commit came over
+    assert_eq!(
+        git_out(&public, &["log", "--format=%s", "feature"]),
+        "shared change\nroot"
+    );
+    assert_eq!(git_out(&public, &["rev-parse", "feature~1"]), public_root);
+    assert_eq!(git_out(&public, &["show", "feature:lib.txt"]), "shared");
+    assert_eq!(git_out(&public, &["notes", "show", "feature"]), "reviewed");
+    assert!(git_out(&public, &["for-each-ref", "refs/extract/"]).is_empty());
+    assert_eq!(git_out(&public, &["worktree", "list"]).lines().count(), 1);
+    assert!(
+        Command::new("git")
+            .arg("-C")
+            .arg(&internal)
+            .args(["show-ref", "--verify", "refs/heads/feature"])
+            .output()
+            .unwrap()
+            .stdout
+            .is_empty()
+    );
+}
+
+#[test]
+fn e2e_subdir_target_rewrites_paths() {
+    let td = init_repo();
+    let repo = td.path();
+    fs::create_dir_all(repo.join("libs/parser")).unwrap();
+    fs::write(repo.join("libs/parser/lib.rs"), "v1\n").unwrap();
+    git(repo, &["add", "."]);
+    git(repo, &["commit", "-m", "add parser"]);
+    let base = git_out(repo, &["rev-parse", "HEAD"]);
+
+    fs::write(repo.join("libs/parser/lib.rs"), "v2\n").unwrap();
+    fs::write(repo.join("app.txt"), "app\n").unwrap();
+    git(repo, &["add", "."]);
+    git(repo, &["commit", "-m", "parser v2 and app"]);
+    fs::write(repo.join("app.txt"), "app 2\n").unwrap();
+    git(repo, &["commit", "-am", "app only"]);
+    let mixed = git_out(repo, &["rev-parse", "--short", "HEAD~1"]);
+    let app = git_out(repo, &["rev-parse", "--short", "HEAD"]);
+
+    run_extract(
+        repo,
+        &format!(
+            "target 1 parser subdir=libs/parser\n1 {mixed} parser v2 and app\n1 {app} app only\n"
+        ),
+        &["--base", &base],
+    )
+    .success()
+    .stderr(contains("changes nothing in libs/parser"))
+    .stdout(contains("parser: 1 commits"));
+
+    // a root commit with the directory as it was at base, then the rewritten pick
+    assert_eq!(
+        git_out(repo, &["log", "--format=%s", "parser"]),
+        format!("parser v2 and app\nExtract libs/parser at {}", &base[..7])
+    );
+    assert_eq!(
+        git_out(repo, &["ls-tree", "--name-only", "parser"]),
+        "lib.rs"
+    );
+    assert_eq!(git_out(repo, &["show", "parser:lib.rs"]), "v2");
+    assert_eq!(git_out(repo, &["show", "parser~1:lib.rs"]), "v1");
+}
+
+#[test]
+fn e2e_sign_copies_of_signed_commits() {
+    let (td, base) = init_repo_with_root();
+    let repo = td.path();
+    let key = repo.join(".git").join("signing-key");
+    let keygen = Command::new("ssh-keygen")
+        .args(["-q", "-t", "ed25519", "-N", "", "-f"])
+        .arg(&key)
+        .output();
+    if !keygen.is_ok_and(|out| out.status.success()) {
+        eprintln!("ssh-keygen unavailable; skipping");
+        return;
+    }
+    git(repo, &["config", "gpg.format", "ssh"]);
+    git(repo, &["config", "user.signingKey", key.to_str().unwrap()]);
+    fs::write(repo.join("a.txt"), "a\n").unwrap();
+    git(repo, &["add", "a.txt"]);
+    git(repo, &["commit", "-q", "-S", "-m", "signed change"]);
+    let sha = git_out(repo, &["rev-parse", "--short", "HEAD"]);
+    let signed = |branch: &str| git_out(repo, &["cat-file", "commit", branch]).contains("gpgsig");
+
+    for (branch, flag, expect_signed) in [("plain", "--no-sign", false), ("signed", "--sign", true)]
+    {
+        let assert = run_extract(
+            repo,
+            &format!("target 1 {branch}\n1 {sha} signed change\n"),
+            &["--base", &base, flag],
+        )
+        .success();
+        if expect_signed {
+            assert.stderr(contains("without a signature").not());
+        } else {
+            assert.stderr(contains(
+                "plain: 1 signed commit(s) were copied without a signature",
+            ));
+        }
+        assert_eq!(signed(branch), expect_signed);
+    }
+
+    // ssh signing without a key is refused before anything is written
+    git(repo, &["config", "--unset", "user.signingKey"]);
+    run_extract(
+        repo,
+        &format!("target 1 nokey\n1 {sha} signed change\n"),
+        &["--base", &base, "--sign"],
+    )
+    .failure()
+    .stderr(contains("needs user.signingKey"));
+}
+
+#[test]
+fn e2e_committer_date_reset_author_and_signoff() {
+    let (td, base) = init_repo_with_root();
+    let repo = td.path();
+    fs::write(repo.join("a.txt"), "a\n").unwrap();
+    git(repo, &["add", "a.txt"]);
+    git(
+        repo,
+        &[
+            "commit",
+            "-q",
+            "--author=Original <original@example.com>",
+            "--date=2001-02-03T04:05:06+0000",
+            "-m",
+            "old change",
+        ],
+    );
+    let sha = git_out(repo, &["rev-parse", "--short", "HEAD"]);
+
+    let extract = |branch: &str, flags: &[&str]| {
+        let routing = format!("target 1 {branch}\n1 {sha} old change\n");
+        run_extract(
+            repo,
+            &routing,
+            &[&["--base", base.as_str()], flags].concat(),
+        )
+        .success();
+    };
+
+    extract("dated", &["--committer-date-is-author-date", "--signoff"]);
+    assert_eq!(
+        git_out(repo, &["log", "-1", "--format=%an|%at|%ct", "dated"]),
+        "Original|981173106|981173106"
+    );
+    assert!(
+        git_out(repo, &["log", "-1", "--format=%B", "dated"])
+            .ends_with("Signed-off-by: Tester <test@example.com>")
+    );
+
+    extract("mine", &["--reset-author"]);
+    assert_eq!(
+        git_out(repo, &["log", "-1", "--format=%an <%ae>|%s", "mine"]),
+        "Tester <test@example.com>|old change"
+    );
+}
+
+#[test]
+fn e2e_record_origin_trailer_and_notes() {
+    let (td, base) = init_repo_with_root();
+    let repo = td.path();
+    fs::write(repo.join("a.txt"), "a\n").unwrap();
+    git(repo, &["add", "a.txt"]);
+    git(repo, &["commit", "-q", "-m", "change"]);
+    let full = git_out(repo, &["rev-parse", "HEAD"]);
+    let sha = git_out(repo, &["rev-parse", "--short", "HEAD"]);
+    git(repo, &["notes", "add", "-m", "reviewed", "HEAD"]);
+    git(repo, &["config", "notes.rewriteRef", "refs/notes/commits"]);
+
+    for (branch, mode) in [("traced", "trailer"), ("noted", "notes")] {
+        run_extract(
+            repo,
+            &format!("target 1 {branch}\n1 {sha} change\n"),
+            &["--base", &base, "--record-origin", mode],
+        )
+        .success();
+        // existing notes follow the commit either way
+        assert_eq!(git_out(repo, &["notes", "show", branch]), "reviewed");
+    }
+
+    assert_eq!(
+        git_out(
+            repo,
+            &[
+                "log",
+                "-1",
+                "--format=%(trailers:key=Extracted-From,valueonly)",
+                "traced"
+            ]
+        ),
+        full
+    );
+    let note = git_out(repo, &["notes", "--ref=extract", "show", "noted"]);
+    assert!(note.starts_with(&format!("Extracted-From: {full}\nExtract-Session: ")));
+    assert_eq!(
+        git_out(repo, &["log", "-1", "--format=%B", "noted"]),
+        "change"
+    );
+}
+
+#[test]
+fn e2e_target_prefix_and_trailer() {
+    let (td, base) = init_repo_with_root();
+    let repo = td.path();
+    fs::write(repo.join("a.txt"), "a\n").unwrap();
+    git(repo, &["add", "a.txt"]);
+    git(repo, &["commit", "-q", "-m", "add login form"]);
+    let sha = git_out(repo, &["rev-parse", "--short", "HEAD"]);
+
+    run_extract(
+        repo,
+        &format!("target 1 feature-login prefix=\"[AUTH-12] \" trailer=\"Issue: AUTH-12\"\n1 {sha} add login form\n"),
+        &["--base", &base],
+    )
+    .success();
+    assert_eq!(
+        git_out(repo, &["log", "-1", "--format=%B", "feature-login"]),
+        "[AUTH-12] add login form\n\nIssue: AUTH-12"
+    );
+}
+
+#[test]
+fn e2e_squash_targets() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let (td, base) = init_repo_with_root();
+    let repo = td.path();
+    let mut shas = Vec::new();
+    for name in ["one", "two", "three"] {
+        fs::write(repo.join(format!("{name}.txt")), name).unwrap();
+        git(repo, &["add", "."]);
+        git(
+            repo,
+            &[
+                "commit",
+                "-q",
+                "--author=Alice <alice@example.com>",
+                "--date=2001-02-03T04:05:06+0000",
+                "-m",
+                &format!("fix {name}"),
+            ],
+        );
+        shas.push(git_out(repo, &["rev-parse", "--short", "HEAD"]));
+    }
+
+    run_extract(
+        repo,
+        &format!(
+            "target 1 fixes squash trailer=\"Issue: FIX-1\"\n1 {} fix one\n1 {} fix two\ncurrent {} fix three\n",
+            shas[0], shas[1], shas[2]
+        ),
+        &["--base", &base],
+    )
+    .success()
+    .stdout(contains("fixes: 1 commits"));
+    assert_eq!(git_out(repo, &["rev-list", "--count", "fixes"]), "2");
+    assert_eq!(
+        git_out(repo, &["log", "-1", "--format=%B", "fixes"]),
+        "fix one\n\n* fix one\n* fix two\n\nIssue: FIX-1"
+    );
+    assert_eq!(
+        git_out(
+            repo,
+            &[
+                "log",
+                "-1",
+                "--format=%an <%ae> %ad",
+                "--date=unix",
+                "fixes"
+            ]
+        ),
+        "Alice <alice@example.com> 981173106"
+    );
+    assert_eq!(git_out(repo, &["show", "fixes:two.txt"]), "two");
+
+    // --squash-targets=edit runs the editor on the generated message
+    let editor = repo.join(".git").join("editor.sh");
+    fs::write(&editor, "#!/bin/sh\nsed -i '1s/.*/All fixes/' \"$1\"\n").unwrap();
+    fs::set_permissions(&editor, fs::Permissions::from_mode(0o755)).unwrap();
+    run_extract(
+        repo,
+        &format!(
+            "target 1 all\n1 {} fix one\n1 {} fix two\n1 {} fix three\n",
+            shas[0], shas[1], shas[2]
+        ),
+        &[
+            "--base",
+            &base,
+            "--squash-targets=edit",
+            "--committer-date-is-author-date",
+            "--signoff",
+            "--editor",
+            editor.to_str().unwrap(),
+        ],
+    )
+    .success();
+    assert_eq!(
+        git_out(repo, &["log", "-1", "--format=%B", "all"]),
+        "All fixes\n\n* fix one\n* fix two\n* fix three\n\nSigned-off-by: Tester <test@example.com>"
+    );
+    assert_eq!(
+        git_out(repo, &["log", "-1", "--format=%an %ct", "all"]),
+        "Alice 981173106"
+    );
+
+    // --reset-author makes the runner the author of the squashed commit too; a bare
+    // --squash-targets leaves the next argument to the targets
+    run_extract(
+        repo,
+        &format!(
+            "1 {} fix one\n1 {} fix two\n1 {} fix three\n",
+            shas[0], shas[1], shas[2]
+        ),
+        &[
+            "--base",
+            &base,
+            "--reset-author",
+            "--squash-targets",
+            "redo",
+        ],
+    )
+    .success();
+    assert_eq!(
+        git_out(repo, &["log", "-1", "--format=%an", "redo"]),
+        "Tester"
+    );
+}
+
+#[test]
+fn e2e_autosquash_folds_fixups_into_their_commit() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let (td, base) = init_repo_with_root();
+    let repo = td.path();
+    let commit = |file: &str, content: &str, message: &str| {
+        fs::write(repo.join(file), content).unwrap();
+        git(repo, &["add", file]);
+        git(repo, &["commit", "-q", "-m", message]);
+    };
+    commit("login.txt", "login\n", "add login");
+    commit("other.txt", "other\n", "unrelated work");
+    commit("login.txt", "login fixed\n", "fixup! add login");
+    commit(
+        "login.txt",
+        "login final\n",
+        "squash! add login\n\nHandle empty passwords.",
+    );
+
+    // route only the login commit; its fixup/squash lines come right below it and follow it
+    let editor = repo.join(".git").join("editor.sh");
+    fs::write(
+        &editor,
+        "#!/bin/sh\ncp \"$1\" .git/rendered.txt\nsed -i -e '1i target 1 login' -e 's/^current \\([0-9a-f]*\\) add login$/1 \\1 add login/' \"$1\"\n",
+    )
+    .unwrap();
+    fs::set_permissions(&editor, fs::Permissions::from_mode(0o755)).unwrap();
+    let output = Command::new(assert_cmd::cargo::cargo_bin!("git-extract"))
+        .current_dir(repo)
+        .args([
+            "--base",
+            base.as_str(),
+            "--editor",
+            editor.to_str().unwrap(),
+        ])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let rendered = fs::read_to_string(repo.join(".git").join("rendered.txt")).unwrap();
+    let kinds: Vec<&str> = rendered
+        .lines()
+        .filter_map(|l| l.split_whitespace().next())
+        .collect();
+    assert_eq!(kinds, ["current", "fixup", "squash", "current"]);
+
+    assert_eq!(git_out(repo, &["rev-list", "--count", "login"]), "2");
+    assert_eq!(
+        git_out(repo, &["log", "-1", "--format=%B", "login"]),
+        "add login\n\nHandle empty passwords."
+    );
+    assert_eq!(git_out(repo, &["show", "login:login.txt"]), "login final");
+    assert!(
+        git_out(repo, &["ls-tree", "--name-only", "login"])
+            .lines()
+            .all(|f| f != "other.txt")
+    );
+}
+
+#[test]
+fn e2e_fixup_of_a_skipped_commit_stays_a_commit() {
+    let (td, base) = init_repo_with_root();
+    let repo = td.path();
+    let commit = |file: &str, content: &str, message: &str| {
+        fs::write(repo.join(file), content).unwrap();
+        git(repo, &["add", file]);
+        git(repo, &["commit", "-q", "-m", message]);
+        git_out(repo, &["rev-parse", "--short", "HEAD"])
+    };
+    let a = commit("a.txt", "a\n", "add a");
+    let b = commit("b.txt", "b\n", "add b");
+    let fix = commit("b.txt", "b fixed\n", "fixup! add b");
+    // feature already carries B, so it is skipped and the fixup has nothing to fold into
+    git(repo, &["branch", "feature", &base]);
+    git(repo, &["checkout", "-q", "feature"]);
+    git(repo, &["cherry-pick", &b]);
+    git(repo, &["checkout", "-q", "-"]);
+
+    run_extract(
+        repo,
+        &format!("feature {a} add a\nfeature {b} add b\nfixup {fix} fixup! add b\n"),
+        &["--base", &base],
+    )
+    .success()
+    .stderr(contains("keeping it as a commit"))
+    .stdout(contains("feature: 2 commits (1 skipped)"));
+    assert_eq!(
+        git_out(repo, &["log", "--format=%s", "feature"]),
+        "fixup! add b\nadd a\nadd b\nroot"
+    );
+    assert_eq!(
+        git_out(repo, &["show", "--name-only", "--format=", "feature~1"]),
+        "a.txt"
+    );
+}
+
+#[test]
+fn e2e_git_config_defaults() {
+    let (td, _) = init_repo_with_root();
+    let repo = td.path();
+    git(repo, &["branch", "stable"]);
+    fs::write(repo.join("a.txt"), "a\n").unwrap();
+    git(repo, &["add", "a.txt"]);
+    git(repo, &["commit", "-q", "-m", "first"]);
+    fs::write(repo.join("b.txt"), "b\n").unwrap();
+    git(repo, &["add", "b.txt"]);
+    git(repo, &["commit", "-q", "-m", "second"]);
+    let first = git_out(repo, &["rev-parse", "--short", "HEAD~1"]);
+    let first_full = git_out(repo, &["rev-parse", "HEAD~1"]);
+    let second = git_out(repo, &["rev-parse", "--short", "HEAD"]);
+
+    // without --base there is no main/master here; extract.base supplies it
+    git(repo, &["config", "extract.base", "stable"]);
+    git(repo, &["config", "extract.branchPrefix", "fw/"]);
+    git(repo, &["config", "extract.recordOrigin", "trailer"]);
+    git(
+        repo,
+        &["config", "extract.group.auth.targets", "login, fw/logout"],
+    );
+    git(repo, &["config", "extract.noCurrent", "true"]);
+
+    // alias 1 is fw/login from the group; the unassigned-by-alias second commit stays
+    // "current", which extract.noCurrent turns into a drop
+    let routing = format!("1 {first} first\ncurrent {second} second\n");
+    run_extract(repo, &routing, &["@auth"])
+        .success()
+        .stdout(contains("fw/login: 1 commits"));
+    assert_eq!(
+        git_out(repo, &["rev-parse", "fw/login~1"]),
+        git_out(repo, &["rev-parse", "stable"])
+    );
+    assert_eq!(
+        git_out(
+            repo,
+            &[
+                "log",
+                "-1",
+                "--format=%(trailers:key=Extracted-From,valueonly)",
+                "fw/login"
+            ]
+        ),
+        first_full
+    );
+
+    // an unknown group and a bad enum value are reported
+    run_extract(repo, &routing, &["@nope", "--dry-run"])
+        .failure()
+        .stderr(contains("unknown target group nope"));
+    git(repo, &["config", "extract.onConflict", "panic"]);
+    run_extract(repo, &routing, &["--dry-run"])
+        .failure()
+        .stderr(contains("invalid extract.onConflict value panic"));
+}
+
+#[test]
+fn e2e_new_dest_uses_branch_template() {
+    let (td, _) = init_repo_with_root();
+    let repo = td.path();
+    git(repo, &["branch", "-M", "main"]);
+    git(repo, &["checkout", "-q", "-b", "work"]);
+    fs::write(repo.join("a.txt"), "a\n").unwrap();
+    git(repo, &["add", "a.txt"]);
+    git(repo, &["commit", "-q", "-m", "Add OAuth login"]);
+    fs::write(repo.join("b.txt"), "b\n").unwrap();
+    git(repo, &["add", "b.txt"]);
+    git(repo, &["commit", "-q", "-m", "Fix typo in docs"]);
+    let first = git_out(repo, &["rev-parse", "--short", "HEAD~1"]);
+    let second = git_out(repo, &["rev-parse", "--short", "HEAD"]);
+
+    git(repo, &["config", "extract.branchTemplate", "{user}/{slug}"]);
+    git(repo, &["config", "extract.branchPrefix", "wip-"]);
+    run_extract(
+        repo,
+        &format!("target 1 docs\nnew {first} Add OAuth login\n1 {second} Fix typo in docs\n"),
+        &[],
+    )
+    .success()
+    .stdout(contains("wip-test/add-oauth-login: 1 commits"))
+    .stdout(contains("wip-docs: 1 commits"));
+    assert_eq!(
+        git_out(
+            repo,
+            &["log", "-1", "--format=%s", "wip-test/add-oauth-login"]
+        ),
+        "Add OAuth login"
+    );
+}
+
+#[test]
+fn e2e_invalid_target_branches_are_reported_together() {
+    let (td, _) = init_repo_with_root();
+    let repo = td.path();
+    git(repo, &["branch", "-M", "main"]);
+    git(repo, &["branch", "team/auth"]);
+    git(repo, &["checkout", "-q", "-b", "work"]);
+    let mut shas = Vec::new();
+    for name in ["a", "b", "c", "d", "e"] {
+        fs::write(repo.join(name), "x\n").unwrap();
+        git(repo, &["add", name]);
+        git(repo, &["commit", "-q", "-m", name]);
+        shas.push(git_out(repo, &["rev-parse", "--short", "HEAD"]));
+    }
+    run_extract(
+        repo,
+        &format!(
+            "team {} a\nbad..name {} b\nwork {} c\napi {} d\napi/v2 {} e\n",
+            shas[0], shas[1], shas[2], shas[3], shas[4]
+        ),
+        &[],
+    )
+    .failure()
+    .stderr(contains("team: clashes with existing refs/heads/team/auth"))
+    .stderr(contains("bad..name: not a valid branch name"))
+    .stderr(contains("work: is a source branch"))
+    .stderr(contains("api: clashes with target api/v2"));
+    assert!(git_out(repo, &["branch", "--list", "api*"]).is_empty());
+    assert!(state_file(repo).is_none());
+}
+
+#[test]
+fn e2e_target_checked_out_in_other_worktree() {
+    let (td, _) = init_repo_with_root();
+    let repo = td.path();
+    git(repo, &["branch", "-M", "main"]);
+    git(repo, &["branch", "feature"]);
+    git(repo, &["checkout", "-q", "-b", "work"]);
+    fs::write(repo.join("a.txt"), "a\n").unwrap();
+    git(repo, &["add", "a.txt"]);
+    git(repo, &["commit", "-q", "-m", "add a"]);
+    let sha = git_out(repo, &["rev-parse", "--short", "HEAD"]);
+    let other = td.path().join(".git").join("feature-wt");
+    git(
+        repo,
+        &["worktree", "add", "-q", other.to_str().unwrap(), "feature"],
+    );
+    let routing = format!("feature {sha} add a\n");
+    let run = |extra: &[&str]| run_extract(repo, &routing, extra);
+
+    // local changes there: refuse before anything is written
+    fs::write(other.join("README"), "edited\n").unwrap();
+    run(&[])
+        .failure()
+        .stderr(contains("checked out in a worktree with local changes"))
+        .stderr(contains("--force"));
+    assert_eq!(
+        git_out(repo, &["rev-parse", "feature"]),
+        git_out(repo, &["rev-parse", "main"])
+    );
+    assert!(state_file(repo).is_none());
+
+    // clean: the branch moves and that worktree follows
+    git(&other, &["checkout", "--", "README"]);
+    run(&[]).success().stdout(contains("updated worktree"));
+    assert_eq!(fs::read_to_string(other.join("a.txt")).unwrap(), "a\n");
+    assert!(git_out(&other, &["status", "--porcelain"]).is_empty());
+
+    // --force moves it anyway and leaves the dirty worktree alone
+    git(&other, &["reset", "-q", "--hard", "main"]);
+    fs::write(other.join("README"), "edited\n").unwrap();
+    run(&["--force"])
+        .success()
+        .stderr(contains("warning: feature is checked out in"));
+    assert_eq!(
+        git_out(repo, &["log", "-1", "--format=%s", "feature"]),
+        "add a"
+    );
+    assert_eq!(
+        fs::read_to_string(other.join("README")).unwrap(),
+        "edited\n"
+    );
+}
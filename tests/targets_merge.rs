@@ -1,10 +1,16 @@
 use git_extract::cli::Args;
+use git_extract::config::ExtractConfig;
+use git_extract::conflict::OnConflict;
 use git_extract::routing::TargetDefs;
+use git_extract::worktree_apply::{EmptyMode, RecordOrigin};
 
 #[test]
 fn merges_positional_and_flag_targets_dedup() {
     let args = Args {
         base: None,
+        source: vec![],
+        range: None,
+        from_mbox: None,
         default_current: false,
         no_current: false,
         targets: vec!["a".into(), "b".into()],
@@ -13,12 +19,28 @@ fn merges_positional_and_flag_targets_dedup() {
         dry_run: false,
         allow_dirty: false,
         routing_file: None,
+        keep_going: false,
+        on_conflict: OnConflict::Stop,
+        strategy: None,
+        strategy_option: vec![],
+        conflict_style: None,
+        empty: EmptyMode::Drop,
+        sign: false,
+        no_sign: false,
+        committer_date_is_author_date: false,
+        reset_author: false,
+        signoff: false,
+        record_origin: RecordOrigin::None,
+        squash_targets: None,
+        force: false,
         r#continue: false,
+        session: None,
         abort: false,
+        repair: false,
         no_chdir_conflict: false,
     };
 
-    let defs = TargetDefs::from_args(&args);
+    let defs = TargetDefs::from_args(&args, &ExtractConfig::default()).unwrap();
     let branches: Vec<String> = defs.targets.iter().map(|t| t.branch.clone()).collect();
     assert_eq!(branches, vec!["a", "b", "c"]);
     let aliases: Vec<u32> = defs.targets.iter().map(|t| t.alias).collect();